@@ -13,18 +13,26 @@ use axum::{
 };
 use bitcoin::{consensus::deserialize, OutPoint, Transaction, Txid};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
+use rocksdb::DB;
 use serde_json::{json, Value};
 use store::database::{DatabaseError, MESSAGE_PREFIX, TRANSACTION_TO_BLOCK_TX_PREFIX};
 use tower_http::cors::CorsLayer;
-use transaction::message::OpReturnMessage;
+use transaction::message::{ContractType, OpReturnMessage};
 
-// TODO: The database lock could possibly slowing down indexing. Add cache or rate limit for the API.
 #[derive(Clone)]
 pub struct APIState {
-    pub database: Arc<Mutex<Database>>,
+    pub db: Arc<DB>,
+    pub mempool: Arc<Mutex<Mempool>>,
     pub rpc: Arc<Client>,
 }
 
+impl APIState {
+    /// Read-only database at the last committed block, the handlers never wait for the indexer.
+    pub fn snapshot(&self) -> Arc<Mutex<Database>> {
+        Arc::new(Mutex::new(Database::snapshot(&self.db)))
+    }
+}
+
 #[serde_with::skip_serializing_none]
 #[derive(Serialize, Deserialize)]
 pub struct ContractInfo {
@@ -36,10 +44,13 @@ pub struct ContractInfo {
     pub asset: Option<Vec<u8>>,
 }
 
+#[serde_with::skip_serializing_none]
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(rename_all = "snake_case")]
 pub struct CollateralizedSimple {
     pub assets: Vec<InputAssetSimple>,
+    // in basis points of the swap input
+    pub swap_fee: Option<U128>,
 }
 
 #[serde_with::skip_serializing_none]
@@ -63,7 +74,15 @@ struct QueryOptions {
     show_contract_info: Option<bool>,
 }
 
-pub async fn run_api(database: Arc<Mutex<Database>>) -> Result<(), std::io::Error> {
+#[derive(Deserialize)]
+struct PaginationOptions {
+    offset: Option<u64>,
+    limit: Option<u64>,
+}
+
+const NFT_TOKENS_PAGE_LIMIT: u64 = 100;
+
+pub async fn run_api(db: Arc<DB>, mempool: Arc<Mutex<Mempool>>) -> Result<(), std::io::Error> {
     let rpc = Client::new(
         CONFIG.btc_rpc_url.as_str(),
         Auth::UserPass(
@@ -79,7 +98,8 @@ pub async fn run_api(database: Arc<Mutex<Database>>) -> Result<(), std::io::Erro
     })?;
 
     let shared_state = APIState {
-        database,
+        db,
+        mempool,
         rpc: Arc::new(rpc),
     };
     let app = Router::new()
@@ -89,11 +109,19 @@ pub async fn run_api(database: Arc<Mutex<Database>>) -> Result<(), std::io::Erro
         .route("/blocktx/ticker/:ticker", get(get_block_tx_by_ticker))
         .route("/assets/:txid/:vout", get(get_assets))
         .route("/asset-contract/:block/:tx", get(get_asset_contract))
+        .route("/nft/:block/:tx/tokens", get(get_nft_tokens))
+        .route("/nft/:block/:tx/token/:serial", get(get_nft_token))
         .route(
             "/collateralized/:block/:tx",
             get(get_collateralized_contract),
         )
+        .route(
+            "/collateralized/:block/:tx/accounts",
+            get(get_contract_collateral_accounts),
+        )
         .route("/validate-tx", post(validate_tx))
+        .route("/mempool/tx/:txid", get(mempool_tx_result))
+        .route("/mempool/assets/:txid/:vout", get(get_mempool_assets))
         .with_state(shared_state.clone());
 
     #[cfg(feature = "helper-api")]
@@ -123,11 +151,11 @@ async fn tx_result(
     State(state): State<APIState>,
     Path(txid): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let blocktx: Result<BlockTxTuple, DatabaseError> = state
-        .database
-        .lock()
-        .await
-        .get(TRANSACTION_TO_BLOCK_TX_PREFIX, txid.as_str());
+    let database = Database::snapshot(&state.db);
+    let block_height = database.block_height();
+
+    let blocktx: Result<BlockTxTuple, DatabaseError> =
+        database.get(TRANSACTION_TO_BLOCK_TX_PREFIX, txid.as_str());
 
     let blocktx = if let Ok(blocktx) = blocktx {
         BlockTx {
@@ -138,20 +166,17 @@ async fn tx_result(
         return Err(StatusCode::NOT_FOUND);
     };
 
-    let message: Result<MessageDataOutcome, DatabaseError> = state
-        .database
-        .lock()
-        .await
-        .get(MESSAGE_PREFIX, blocktx.to_string().as_str());
+    let message: Result<MessageDataOutcome, DatabaseError> =
+        database.get(MESSAGE_PREFIX, blocktx.to_string().as_str());
 
     if let Ok(message) = message {
         if message.flaw.is_some() {
             Ok(Json(
-                json!({"is_valid": false, "message": message, "block_tx": blocktx.to_string()}),
+                json!({"is_valid": false, "message": message, "block_tx": blocktx.to_string(), "block_height": block_height}),
             ))
         } else {
             Ok(Json(
-                json!({"is_valid": true, "message": message, "block_tx": blocktx.to_string()}),
+                json!({"is_valid": true, "message": message, "block_tx": blocktx.to_string(), "block_height": block_height}),
             ))
         }
     } else {
@@ -163,14 +188,14 @@ async fn get_block_tx(
     State(state): State<APIState>,
     Path((block, tx)): Path<(u64, u32)>,
 ) -> Result<Json<Value>, StatusCode> {
-    let message: Result<MessageDataOutcome, DatabaseError> = state
-        .database
-        .lock()
-        .await
-        .get(MESSAGE_PREFIX, BlockTx { block, tx }.to_string().as_str());
+    let database = Database::snapshot(&state.db);
+    let message: Result<MessageDataOutcome, DatabaseError> =
+        database.get(MESSAGE_PREFIX, BlockTx { block, tx }.to_string().as_str());
 
     if let Ok(message) = message {
-        Ok(Json(json!({"is_valid": true, "message": message})))
+        Ok(Json(
+            json!({"is_valid": true, "message": message, "block_height": database.block_height()}),
+        ))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
@@ -180,11 +205,13 @@ async fn get_block_tx_by_ticker(
     State(state): State<APIState>,
     Path(ticker): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database.clone(), true).await;
+    let database = state.snapshot();
+    let updater = Updater::new(database.clone(), true).await;
     let block_tx = updater.get_contract_block_tx_by_ticker(ticker).await;
 
     if let Ok(block_tx) = block_tx {
-        let message: Result<MessageDataOutcome, DatabaseError> = state.database.lock().await.get(
+        let database = database.lock().await;
+        let message: Result<MessageDataOutcome, DatabaseError> = database.get(
             MESSAGE_PREFIX,
             BlockTx {
                 block: block_tx.0,
@@ -195,7 +222,9 @@ async fn get_block_tx_by_ticker(
         );
 
         if let Ok(message) = message {
-            Ok(Json(json!({"is_valid": true, "message": message})))
+            Ok(Json(
+                json!({"is_valid": true, "message": message, "block_height": database.block_height()}),
+            ))
         } else {
             Err(StatusCode::NOT_FOUND)
         }
@@ -209,7 +238,7 @@ async fn get_assets(
     Path((txid, vout)): Path<(String, u32)>,
     options: Query<QueryOptions>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database, true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
     let outpoint = OutPoint {
         txid: Txid::from_str(txid.as_str()).unwrap(),
         vout,
@@ -276,7 +305,20 @@ async fn get_assets(
         Err(_) => (),
     }
 
-    let mut result = json!({"assets": asset_list});
+    let mut result = json!({
+        "assets": asset_list,
+        "block_height": updater.get_last_indexed_block().await,
+    });
+
+    if let Ok(nft_tokens) = updater.get_nft_tokens(&outpoint).await {
+        if !nft_tokens.tokens.is_empty() {
+            result["nft_tokens"] = json!(nft_tokens
+                .tokens
+                .iter()
+                .map(nft_token_key)
+                .collect::<Vec<String>>());
+        }
+    }
 
     if state_keys.contract_ids.len() > 0 {
         result["state_keys"] = json!(state_keys
@@ -296,29 +338,103 @@ async fn get_asset_contract(
     State(state): State<APIState>,
     Path((block, tx)): Path<(u64, u32)>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database, true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
     if let Ok(asset_contract_data) = updater.get_asset_contract_data(&(block, tx)).await {
         let contract_info = updater
             .get_contract_info_by_block_tx((block, tx))
             .await
             .unwrap();
+        let block_height = updater.get_last_indexed_block().await;
+
+        let mut result = json!({ "asset": asset_contract_data, "contract_info": contract_info, "block_height": block_height });
 
         if let Ok(collateralized_contract_data) =
             updater.get_collateralized_contract_data(&(block, tx)).await
         {
-            let contract_info = updater
-                .get_contract_info_by_block_tx((block, tx))
-                .await
-                .unwrap();
+            result["collateralized"] = json!(collateralized_contract_data);
+        }
 
-            Ok(Json(
-                json!({ "asset": asset_contract_data, "collateralized": collateralized_contract_data, "contract_info": contract_info }),
-            ))
-        } else {
-            Ok(Json(
-                json!({ "asset": asset_contract_data, "contract_info": contract_info }),
-            ))
+        if let Ok(message) = updater.get_message(&(block, tx)).await {
+            if let Some(ContractType::Nft(_)) = message
+                .contract_creation
+                .map(|contract_creation| contract_creation.contract_type)
+            {
+                if let Ok(nft_contract_data) = updater.get_nft_contract_data(&(block, tx)).await {
+                    result["nft"] = json!(nft_contract_data);
+                }
+            }
+        }
+
+        Ok(Json(result))
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Tokens of an nft collection in serial order, `limit` is capped to a page of 100 tokens.
+async fn get_nft_tokens(
+    State(state): State<APIState>,
+    Path((block, tx)): Path<(u64, u32)>,
+    options: Query<PaginationOptions>,
+) -> Result<Json<Value>, StatusCode> {
+    let updater = Updater::new(state.snapshot(), true).await;
+
+    let is_nft = updater
+        .get_message(&(block, tx))
+        .await
+        .is_ok_and(|message| {
+            matches!(
+                message
+                    .contract_creation
+                    .map(|contract_creation| contract_creation.contract_type),
+                Some(ContractType::Nft(_))
+            )
+        });
+    if !is_nft {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let minted_supply = updater
+        .get_asset_contract_data(&(block, tx))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .minted_supply as u64;
+
+    let offset = options.offset.unwrap_or(0);
+    let limit = options
+        .limit
+        .unwrap_or(NFT_TOKENS_PAGE_LIMIT)
+        .min(NFT_TOKENS_PAGE_LIMIT);
+
+    let mut tokens = Vec::new();
+    for serial in (offset.saturating_add(1)..=minted_supply).take(limit as usize) {
+        let token_id = ((block, tx), serial);
+        // mints before the tokens were tracked have no token
+        if let Ok(nft_token) = updater.get_nft_token(&token_id).await {
+            tokens.push(json!({ "token_id": nft_token_key(&token_id), "token": nft_token }));
         }
+    }
+
+    Ok(Json(json!({
+        "tokens": tokens,
+        "minted_supply": minted_supply,
+        "block_height": updater.get_last_indexed_block().await,
+    })))
+}
+
+async fn get_nft_token(
+    State(state): State<APIState>,
+    Path((block, tx, serial)): Path<(u64, u32, u64)>,
+) -> Result<Json<Value>, StatusCode> {
+    let updater = Updater::new(state.snapshot(), true).await;
+    let token_id = ((block, tx), serial);
+
+    if let Ok(nft_token) = updater.get_nft_token(&token_id).await {
+        let block_height = updater.get_last_indexed_block().await;
+
+        Ok(Json(
+            json!({ "token_id": nft_token_key(&token_id), "token": nft_token, "block_height": block_height }),
+        ))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
@@ -328,7 +444,7 @@ async fn get_collateralized_contract(
     State(state): State<APIState>,
     Path((block, tx)): Path<(u64, u32)>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database, true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
     if let Ok(collateralized_contract_data) =
         updater.get_collateralized_contract_data(&(block, tx)).await
     {
@@ -336,15 +452,56 @@ async fn get_collateralized_contract(
             .get_contract_info_by_block_tx((block, tx))
             .await
             .unwrap();
+        let block_height = updater.get_last_indexed_block().await;
 
         Ok(Json(
-            json!({ "assets": collateralized_contract_data, "contract_info": contract_info }),
+            json!({ "assets": collateralized_contract_data, "contract_info": contract_info, "block_height": block_height }),
         ))
     } else {
         Err(StatusCode::NOT_FOUND)
     }
 }
 
+/// Collateral accounts of a contract with the outpoints holding them, the accounts can be
+/// liquidated without their owner's outpoint.
+async fn get_contract_collateral_accounts(
+    State(state): State<APIState>,
+    Path((block, tx)): Path<(u64, u32)>,
+) -> Result<Json<Value>, StatusCode> {
+    let updater = Updater::new(state.snapshot(), true).await;
+    let contract_id = BlockTx { block, tx }.to_string();
+
+    let mut outpoints: Vec<String> = updater
+        .get_contract_collateral_accounts(&contract_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+        .outpoints
+        .into_iter()
+        .collect();
+    outpoints.sort();
+
+    let mut accounts = Vec::new();
+    for outpoint in outpoints {
+        let Ok(outpoint) = OutPoint::from_str(&outpoint) else {
+            continue;
+        };
+        if let Ok(collateral_accounts) = updater.get_collateral_accounts(&outpoint).await {
+            if let Some(collateral_account) =
+                collateral_accounts.collateral_accounts.get(&contract_id)
+            {
+                accounts.push(
+                    json!({ "outpoint": outpoint.to_string(), "account": collateral_account }),
+                );
+            }
+        }
+    }
+
+    Ok(Json(json!({
+        "accounts": accounts,
+        "block_height": updater.get_last_indexed_block().await,
+    })))
+}
+
 async fn validate_tx(
     State(state): State<APIState>,
     body: String,
@@ -368,16 +525,21 @@ async fn validate_tx(
     if let Ok(op_return_message) = OpReturnMessage::parse_tx(&tx) {
         // Get current block height for validation
         let current_block_tip = state.rpc.get_block_count().unwrap();
-        let mut temp_updater = Updater::new(Arc::clone(&state.database), true).await;
+        let mut temp_updater = Updater::new(state.snapshot(), true).await;
+        let block_height = temp_updater.get_last_indexed_block().await;
         let _ = temp_updater.unallocate_inputs(&tx).await;
         if let Ok(outcome) = temp_updater
             .index(current_block_tip, 1, &tx, Ok(op_return_message))
             .await
         {
             if let Some(flaw) = outcome.flaw {
-                Ok(Json(json!({"is_valid": false, "msg": flaw})))
+                Ok(Json(
+                    json!({"is_valid": false, "msg": flaw, "block_height": block_height}),
+                ))
             } else {
-                Ok(Json(json!({"is_valid": true})))
+                Ok(Json(
+                    json!({"is_valid": true, "block_height": block_height}),
+                ))
             }
         } else {
             Ok(Json(json!({"is_valid": false, "msg": "Error"})))
@@ -389,6 +551,43 @@ async fn validate_tx(
     }
 }
 
+async fn mempool_tx_result(
+    State(state): State<APIState>,
+    Path(txid): Path<String>,
+) -> Result<Json<Value>, StatusCode> {
+    let txid = Txid::from_str(txid.as_str()).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let mempool = state.mempool.lock().await;
+    let Some(pending) = mempool.get(&txid) else {
+        return Err(StatusCode::NOT_FOUND);
+    };
+
+    let is_valid = pending
+        .outcome
+        .as_ref()
+        .and_then(|outcome| outcome.flaw.as_ref())
+        .is_none();
+
+    Ok(Json(
+        json!({"is_valid": is_valid, "message": pending.outcome, "assets": pending.assets}),
+    ))
+}
+
+async fn get_mempool_assets(
+    State(state): State<APIState>,
+    Path((txid, vout)): Path<(String, u32)>,
+) -> Result<Json<Value>, StatusCode> {
+    let outpoint = OutPoint {
+        txid: Txid::from_str(txid.as_str()).map_err(|_| StatusCode::BAD_REQUEST)?,
+        vout,
+    };
+
+    match state.mempool.lock().await.get_asset_list(&outpoint) {
+        Some(asset_list) => Ok(Json(json!({"assets": asset_list}))),
+        None => Err(StatusCode::NOT_FOUND),
+    }
+}
+
 async fn health() -> &'static str {
     "OK"
 }
@@ -1,5 +1,25 @@
-use glittr::run;
+use glittr::{migrate, rollback, run};
+use std::env;
 
 fn main() {
-    run().expect("Main thread run")
+    let args: Vec<String> = env::args().collect();
+
+    match args.get(1).map(String::as_str) {
+        Some("rollback") => {
+            let to_height = match (args.get(2).map(String::as_str), args.get(3)) {
+                (Some("--to-height"), Some(height)) => height.parse::<u64>().ok(),
+                _ => None,
+            };
+
+            match to_height {
+                Some(to_height) => rollback(to_height).expect("Rollback"),
+                None => {
+                    eprintln!("Usage: glittr rollback --to-height <HEIGHT>");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some("migrate") => migrate().expect("Migrate"),
+        _ => run().expect("Main thread run"),
+    }
 }
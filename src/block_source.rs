@@ -0,0 +1,92 @@
+mod blk_file;
+mod prefetch;
+mod rest;
+
+pub use blk_file::BlkFileBlockSource;
+pub use prefetch::{BlockPrefetcher, PrefetchedBlock};
+pub use rest::RestBlockSource;
+
+use super::*;
+
+use bitcoin::{Block, BlockHash};
+use bitcoincore_rpc::{Auth, Client, RpcApi};
+use crate::config::get_bitcoin_network;
+
+/// Where the indexer reads the blocks of the best chain from.
+pub trait BlockSource: Send + Sync {
+    fn get_block_count(&self) -> Result<u64, Box<dyn Error>>;
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn Error>>;
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, Box<dyn Error>>;
+}
+
+/// Reads blocks through the bitcoin RPC `getblock`.
+pub struct RpcBlockSource {
+    rpc: Client,
+}
+
+impl RpcBlockSource {
+    pub fn new(
+        btc_rpc_url: String,
+        btc_rpc_username: String,
+        btc_rpc_password: String,
+    ) -> Result<Self, Box<dyn Error>> {
+        let rpc = Client::new(
+            btc_rpc_url.as_str(),
+            Auth::UserPass(btc_rpc_username, btc_rpc_password),
+        )?;
+
+        Ok(RpcBlockSource { rpc })
+    }
+}
+
+impl BlockSource for RpcBlockSource {
+    fn get_block_count(&self) -> Result<u64, Box<dyn Error>> {
+        Ok(self.rpc.get_block_count()?)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn Error>> {
+        Ok(self.rpc.get_block_hash(height)?)
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, Box<dyn Error>> {
+        Ok(self.rpc.get_block(hash)?)
+    }
+}
+
+/// Block source selected by `block_source` in the settings, defaults to the bitcoin RPC.
+pub fn new_block_source() -> Result<Box<dyn BlockSource>, Box<dyn Error>> {
+    match CONFIG.block_source.as_deref() {
+        None | Some("rpc") => Ok(Box::new(RpcBlockSource::new(
+            CONFIG.btc_rpc_url.clone(),
+            CONFIG.btc_rpc_username.clone(),
+            CONFIG.btc_rpc_password.clone(),
+        )?)),
+        Some("rest") => {
+            let rest_url = CONFIG
+                .btc_rest_url
+                .clone()
+                .ok_or("btc_rest_url is required for the rest block source")?;
+            Ok(Box::new(RestBlockSource::new(rest_url)))
+        }
+        Some("blk") => {
+            let blocks_dir = CONFIG
+                .btc_blocks_dir
+                .clone()
+                .ok_or("btc_blocks_dir is required for the blk block source")?;
+            // the node tells which blocks of the blk files it validated
+            let node = RpcBlockSource::new(
+                CONFIG.btc_rpc_url.clone(),
+                CONFIG.btc_rpc_username.clone(),
+                CONFIG.btc_rpc_password.clone(),
+            )?;
+            Ok(Box::new(BlkFileBlockSource::new(
+                blocks_dir,
+                get_bitcoin_network(),
+                Some(Box::new(node)),
+            )?))
+        }
+        Some(notexist) => Err(format!("Invalid block source in setting: {notexist}").into()),
+    }
+}
@@ -0,0 +1,289 @@
+use super::*;
+
+use bitcoin::{block::Header, consensus::deserialize, hashes::Hash, pow::Work, Network};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::Mutex as StdMutex,
+};
+
+// blocks of the node that might not be visible in the blk files yet
+const MAX_UNWRITTEN_NODE_BLOCKS: usize = 6;
+
+/// Reads blocks straight from the `blocks/` directory of bitcoind.
+/// bitcoind stores blocks out of order, the best chain is rebuilt from the block headers
+/// found in the blk*.dat files. The blk files also hold blocks the node rejected, with a node
+/// the chain ends at the best block of the node, otherwise it follows the most accumulated work.
+pub struct BlkFileBlockSource {
+    blocks_dir: PathBuf,
+    magic: [u8; 4],
+    xor_key: Vec<u8>,
+    node: Option<Box<dyn BlockSource>>,
+    index: StdMutex<BlkIndex>,
+}
+
+struct BlockLocation {
+    file: u32,
+    offset: u64,
+    size: u32,
+    prev_blockhash: BlockHash,
+    work: Work,
+}
+
+#[derive(Default)]
+struct BlkIndex {
+    last_file: u32,
+    scanned: u64,
+    blocks: HashMap<BlockHash, BlockLocation>,
+    chain: Vec<BlockHash>,
+}
+
+impl BlkFileBlockSource {
+    pub fn new(
+        blocks_dir: String,
+        network: Network,
+        node: Option<Box<dyn BlockSource>>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let blocks_dir = PathBuf::from(blocks_dir);
+
+        // bitcoind 28+ obfuscates the block files with the key in xor.dat
+        let xor_key = std::fs::read(blocks_dir.join("xor.dat")).unwrap_or_default();
+
+        let block_source = BlkFileBlockSource {
+            blocks_dir,
+            magic: network.magic().to_bytes(),
+            xor_key,
+            node,
+            index: StdMutex::new(BlkIndex::default()),
+        };
+        block_source.refresh()?;
+
+        Ok(block_source)
+    }
+
+    fn blk_file_path(&self, file: u32) -> PathBuf {
+        self.blocks_dir.join(format!("blk{:05}.dat", file))
+    }
+
+    fn read_at(&self, file: &mut File, offset: u64, buf: &mut [u8]) -> Result<(), Box<dyn Error>> {
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(buf)?;
+
+        if !self.xor_key.is_empty() {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte ^= self.xor_key[(offset as usize + i) % self.xor_key.len()];
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Index the blocks appended since the last refresh, only the last blk file grows.
+    fn refresh(&self) -> Result<(), Box<dyn Error>> {
+        let mut index = self.index.lock().unwrap();
+        let index = &mut *index;
+        let previous_block_count = index.blocks.len();
+
+        loop {
+            let path = self.blk_file_path(index.last_file);
+            if !path.exists() {
+                break;
+            }
+
+            let mut file = File::open(path)?;
+            let len = file.metadata()?.len();
+            let mut offset = index.scanned;
+
+            while offset + 8 <= len {
+                let mut record = [0u8; 8];
+                self.read_at(&mut file, offset, &mut record)?;
+
+                // the rest of the file is preallocated zeros
+                if record[..4] != self.magic {
+                    break;
+                }
+
+                let size = u32::from_le_bytes([record[4], record[5], record[6], record[7]]);
+                // the block is still being written
+                if size < 80 || offset + 8 + size as u64 > len {
+                    break;
+                }
+
+                let mut header = [0u8; 80];
+                self.read_at(&mut file, offset + 8, &mut header)?;
+                let header: Header = deserialize(&header)?;
+
+                index.blocks.insert(
+                    header.block_hash(),
+                    BlockLocation {
+                        file: index.last_file,
+                        offset: offset + 8,
+                        size,
+                        prev_blockhash: header.prev_blockhash,
+                        work: header.work(),
+                    },
+                );
+
+                offset += 8 + size as u64;
+            }
+            index.scanned = offset;
+
+            if !self.blk_file_path(index.last_file + 1).exists() {
+                break;
+            }
+            index.last_file += 1;
+            index.scanned = 0;
+        }
+
+        // the chain of the node is followed instead when there is one
+        if index.blocks.len() != previous_block_count && self.node.is_none() {
+            rebuild_chain(index)?;
+        }
+
+        Ok(())
+    }
+
+    /// Best block of the node already stored in the blk files, the node writes a block to the
+    /// blk files before it becomes the tip but the write might not be visible yet.
+    fn node_tip(&self, node: &dyn BlockSource) -> Result<BlockHash, Box<dyn Error>> {
+        let block_count = node.get_block_count()?;
+        // only the last few blocks can be missing, otherwise the blocks directory is not the
+        // one of the node
+        for height in (0..=block_count).rev().take(MAX_UNWRITTEN_NODE_BLOCKS) {
+            let hash = node.get_block_hash(height)?;
+            if self.index.lock().unwrap().blocks.contains_key(&hash) {
+                return Ok(hash);
+            }
+        }
+
+        Err(format!(
+            "The best block of the node is not in the blocks directory {}",
+            self.blocks_dir.display()
+        )
+        .into())
+    }
+}
+
+fn rebuild_chain(index: &mut BlkIndex) -> Result<(), Box<dyn Error>> {
+    let mut chain_work: HashMap<BlockHash, (u64, Work)> = HashMap::new();
+
+    for hash in index.blocks.keys() {
+        // walk back until a block with known work or the genesis block
+        let mut path = Vec::new();
+        let mut base: Option<(u64, Work)> = None;
+        let mut current = *hash;
+        let connected = loop {
+            if let Some(known) = chain_work.get(&current) {
+                base = Some(*known);
+                break true;
+            }
+
+            let Some(location) = index.blocks.get(&current) else {
+                // the parent is not stored (yet)
+                break false;
+            };
+
+            path.push((current, location.work));
+            if location.prev_blockhash == BlockHash::all_zeros() {
+                break true;
+            }
+            current = location.prev_blockhash;
+        };
+
+        if !connected {
+            continue;
+        }
+
+        for (hash, work) in path.into_iter().rev() {
+            let (height, work) = match base {
+                Some((height, base_work)) => (height + 1, base_work + work),
+                None => (0, work),
+            };
+
+            chain_work.insert(hash, (height, work));
+            base = Some((height, work));
+        }
+    }
+
+    // keep the current tip when another tip has the same work
+    let current_tip = index.chain.last().copied();
+    let Some((tip, _)) = chain_work.iter().max_by(|a, b| {
+        a.1 .1
+            .cmp(&b.1 .1)
+            .then_with(|| (Some(*a.0) == current_tip).cmp(&(Some(*b.0) == current_tip)))
+    }) else {
+        return Ok(());
+    };
+
+    let tip = *tip;
+    follow_chain(index, tip)
+}
+
+/// Make the chain end at `tip`, fails when an ancestor is missing from the blk files, such as
+/// the blocks pruned by the node.
+fn follow_chain(index: &mut BlkIndex, tip: BlockHash) -> Result<(), Box<dyn Error>> {
+    let mut chain = Vec::new();
+    let mut current = tip;
+    while current != BlockHash::all_zeros() {
+        chain.push(current);
+        current = index
+            .blocks
+            .get(&current)
+            .ok_or_else(|| format!("Block {} not found in the blocks directory", current))?
+            .prev_blockhash;
+    }
+    chain.reverse();
+
+    index.chain = chain;
+    Ok(())
+}
+
+impl BlockSource for BlkFileBlockSource {
+    fn get_block_count(&self) -> Result<u64, Box<dyn Error>> {
+        self.refresh()?;
+
+        if let Some(node) = &self.node {
+            let tip = self.node_tip(node.as_ref())?;
+            let mut index = self.index.lock().unwrap();
+            if index.chain.last() != Some(&tip) {
+                follow_chain(&mut index, tip)?;
+            }
+        }
+
+        let index = self.index.lock().unwrap();
+        if index.chain.is_empty() {
+            return Err("No blocks found in the blocks directory".into());
+        }
+
+        Ok(index.chain.len() as u64 - 1)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn Error>> {
+        let index = self.index.lock().unwrap();
+
+        index
+            .chain
+            .get(height as usize)
+            .copied()
+            .ok_or_else(|| format!("Block {} not found in the blocks directory", height).into())
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, Box<dyn Error>> {
+        let (file, offset, size) = {
+            let index = self.index.lock().unwrap();
+            let location = index
+                .blocks
+                .get(hash)
+                .ok_or_else(|| format!("Block {} not found in the blocks directory", hash))?;
+            (location.file, location.offset, location.size)
+        };
+
+        let mut blk_file = File::open(self.blk_file_path(file))?;
+        let mut bytes = vec![0u8; size as usize];
+        self.read_at(&mut blk_file, offset, &mut bytes)?;
+
+        Ok(deserialize(&bytes)?)
+    }
+}
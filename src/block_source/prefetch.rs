@@ -0,0 +1,90 @@
+use super::*;
+
+use std::collections::VecDeque;
+use tokio::{sync::mpsc, task::JoinHandle};
+use transaction::message::OpReturnMessage;
+
+pub struct PrefetchedBlock {
+    pub height: u64,
+    pub hash: BlockHash,
+    pub block: Block,
+    /// Parsed Glittr message of every transaction in the block.
+    pub messages: Vec<Result<OpReturnMessage, Flaw>>,
+}
+
+/// Fetches and parses the blocks ahead of the indexer on worker tasks,
+/// the blocks are received in height order.
+pub struct BlockPrefetcher {
+    receiver: mpsc::Receiver<Result<PrefetchedBlock, String>>,
+    handle: JoinHandle<()>,
+}
+
+impl BlockPrefetcher {
+    pub fn start(
+        block_source: Arc<dyn BlockSource>,
+        from_height: u64,
+        to_height: u64,
+        prefetch_blocks: usize,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(prefetch_blocks);
+
+        let handle = tokio::spawn(async move {
+            let mut fetching = VecDeque::new();
+            let mut next_height = from_height;
+
+            loop {
+                while fetching.len() < prefetch_blocks && next_height <= to_height {
+                    let block_source = Arc::clone(&block_source);
+                    let height = next_height;
+                    fetching.push_back(tokio::task::spawn_blocking(move || {
+                        fetch_block(block_source.as_ref(), height)
+                            .map_err(|error| error.to_string())
+                    }));
+                    next_height += 1;
+                }
+
+                let Some(fetch) = fetching.pop_front() else {
+                    break;
+                };
+
+                let prefetched = match fetch.await {
+                    Ok(prefetched) => prefetched,
+                    Err(error) => Err(error.to_string()),
+                };
+                let failed = prefetched.is_err();
+
+                if sender.send(prefetched).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        BlockPrefetcher { receiver, handle }
+    }
+
+    pub async fn next(&mut self) -> Option<Result<PrefetchedBlock, String>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for BlockPrefetcher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+fn fetch_block(
+    block_source: &dyn BlockSource,
+    height: u64,
+) -> Result<PrefetchedBlock, Box<dyn Error>> {
+    let hash = block_source.get_block_hash(height)?;
+    let block = block_source.get_block(&hash)?;
+    let messages = block.txdata.iter().map(OpReturnMessage::parse_tx).collect();
+
+    Ok(PrefetchedBlock {
+        height,
+        hash,
+        block,
+        messages,
+    })
+}
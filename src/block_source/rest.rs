@@ -0,0 +1,56 @@
+use super::*;
+
+use bitcoin::consensus::deserialize;
+use std::io::Read;
+
+/// Reads binary blocks from the bitcoind REST interface (`-rest`).
+pub struct RestBlockSource {
+    rest_url: String,
+    agent: ureq::Agent,
+}
+
+#[derive(Deserialize)]
+struct ChainInfo {
+    blocks: u64,
+}
+
+impl RestBlockSource {
+    pub fn new(rest_url: String) -> Self {
+        RestBlockSource {
+            rest_url: rest_url.trim_end_matches('/').to_string(),
+            agent: ureq::Agent::new(),
+        }
+    }
+
+    fn get_bytes(&self, path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let response = self
+            .agent
+            .get(&format!("{}/rest/{}", self.rest_url, path))
+            .call()?;
+
+        let mut bytes = Vec::new();
+        response.into_reader().read_to_end(&mut bytes)?;
+
+        Ok(bytes)
+    }
+}
+
+impl BlockSource for RestBlockSource {
+    fn get_block_count(&self) -> Result<u64, Box<dyn Error>> {
+        let chain_info: ChainInfo = serde_json::from_slice(&self.get_bytes("chaininfo.json")?)?;
+
+        Ok(chain_info.blocks)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, Box<dyn Error>> {
+        let bytes = self.get_bytes(&format!("blockhashbyheight/{}.bin", height))?;
+
+        Ok(deserialize(&bytes)?)
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, Box<dyn Error>> {
+        let bytes = self.get_bytes(&format!("block/{}.bin", hash))?;
+
+        Ok(deserialize(&bytes)?)
+    }
+}
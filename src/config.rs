@@ -11,6 +11,12 @@ pub struct Settings {
     pub rocks_db_path: String,
     pub api_url: String,
     pub bitcoin_network: String, // Add this line
+    pub undo_log_retention: Option<u64>,
+    pub zmq_url: Option<String>,
+    pub block_source: Option<String>,
+    pub btc_rest_url: Option<String>,
+    pub btc_blocks_dir: Option<String>,
+    pub prefetch_blocks: Option<usize>,
 }
 
 pub fn get_bitcoin_network() -> Network {
@@ -5,6 +5,9 @@ pub const GLITTR_FIRST_BLOCK_HEIGHT_MAINNET: u64 = 0;
 pub const GLITTR_FIRST_BLOCK_HEIGHT_TESTNET: u64 = 0;
 pub const GLITTR_FIRST_BLOCK_HEIGHT_SIGNET: u64 = 0;
 pub const OP_RETURN_MAGIC_PREFIX: &str = "GLITTR";
+// first byte of the payload, the legacy JSON payloads start with `{`
+pub const OP_RETURN_FORMAT_BORSH: u8 = 0;
+pub const OP_RETURN_FORMAT_BORSH_DEFLATE: u8 = 1;
 
 pub fn first_glittr_height() -> u64 {
     let bitcoin_network = get_bitcoin_network();
@@ -17,3 +20,90 @@ pub fn first_glittr_height() -> u64 {
         _ => GLITTR_FIRST_BLOCK_HEIGHT_REGTEST
     }
 }
+
+// rule sets are activated per network, ahead of the tips when they were scheduled
+pub const RULE_SET_V1_HEIGHT_REGTEST: u64 = 0;
+pub const RULE_SET_V1_HEIGHT_MAINNET: u64 = 980_000;
+pub const RULE_SET_V1_HEIGHT_TESTNET: u64 = 4_900_000;
+pub const RULE_SET_V1_HEIGHT_SIGNET: u64 = 330_000;
+
+/// Consensus behavior that changed after the first glittr block. The default rule set is the
+/// behavior of the first glittr block, the blocks keep the rule set of their height.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RuleSet {
+    /// burns are added to the burned supply of the asset contract
+    pub track_burned_supply: bool,
+    /// mints verify the commitment of the contract and can't replay it
+    pub check_mint_commitments: bool,
+    /// swaps of proportional pools charge the swap fee of the contract
+    pub charge_swap_fees: bool,
+    /// burns returning collateral charge the return collateral fee of the contract
+    pub charge_return_collateral_fees: bool,
+    /// nft contracts can be updated with their access key
+    pub update_nft: bool,
+    /// nft contracts declare an allowlist, a mint limit and a price
+    pub nft_mint_rules: bool,
+    /// nft mints are serial tokens with metadata that are transferred one by one, the mint rules
+    /// apply to the tokens only
+    pub nft_tokens: bool,
+    /// nft contracts declare a royalty on the sales of their tokens
+    pub nft_royalties: bool,
+    /// collateral accounts are topped up, repaid and withdrawn from
+    pub collateral_account_updates: bool,
+    /// collateral account contracts declare a liquidation
+    pub liquidation: bool,
+    /// collateral accounts hold shares of the collateral of the contract, one share per unit of
+    /// collateral before
+    pub collateral_shares: bool,
+}
+
+pub const RULE_SET_V1: RuleSet = RuleSet {
+    track_burned_supply: true,
+    check_mint_commitments: true,
+    charge_swap_fees: true,
+    charge_return_collateral_fees: true,
+    update_nft: true,
+    nft_mint_rules: true,
+    nft_tokens: true,
+    nft_royalties: true,
+    collateral_account_updates: true,
+    liquidation: true,
+    collateral_shares: true,
+};
+
+/// Rule sets by activation height.
+#[derive(Clone, Debug, Default)]
+pub struct Rules {
+    activations: Vec<(u64, RuleSet)>,
+}
+
+impl Rules {
+    pub fn new(mut activations: Vec<(u64, RuleSet)>) -> Self {
+        activations.sort_by_key(|(height, _)| *height);
+        Rules { activations }
+    }
+
+    /// The rule set of the last activation at or below the height.
+    pub fn at(&self, height: u64) -> RuleSet {
+        self.activations
+            .iter()
+            .rev()
+            .find(|(activation_height, _)| *activation_height <= height)
+            .map(|(_, rule_set)| *rule_set)
+            .unwrap_or_default()
+    }
+}
+
+pub fn rules() -> Rules {
+    let bitcoin_network = get_bitcoin_network();
+
+    let rule_set_v1_height = match bitcoin_network {
+        bitcoin::Network::Bitcoin => RULE_SET_V1_HEIGHT_MAINNET,
+        bitcoin::Network::Testnet => RULE_SET_V1_HEIGHT_TESTNET,
+        bitcoin::Network::Signet => RULE_SET_V1_HEIGHT_SIGNET,
+        bitcoin::Network::Regtest => RULE_SET_V1_HEIGHT_REGTEST,
+        _ => RULE_SET_V1_HEIGHT_REGTEST,
+    };
+
+    Rules::new(vec![(rule_set_v1_height, RULE_SET_V1)])
+}
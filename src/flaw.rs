@@ -1,6 +1,6 @@
 use super::*;
 
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum Flaw {
     // parse tx
@@ -86,5 +86,63 @@ pub enum Flaw {
     PoolNotFound,
     AssertValuesMismatch,
     NotFound,
-    EndTimeIsLessThanLiveTime
+    EndTimeIsLessThanLiveTime,
+
+    // the outcomes are stored borsh encoded by variant index, new variants are appended below
+
+    // call type::mint commitment
+    CommitmentMessageNotFound,
+    CommitmentPubkeyMismatch,
+    CommitmentMessageInvalid,
+    CommitmentAlreadyUsed,
+
+    // call type::update nft
+    InvalidBloomFilter,
+
+    // call type::mint nft
+    NotWhitelisted,
+    MintLimitExceeded,
+    MintPriceNotPaid,
+
+    // transfer::nft royalty
+    RoyaltyNotPaid,
+
+    // call type::swap fee
+    SwapFeeInvalid,
+
+    // call type::burn fee
+    FeeOutputNotFound,
+
+    // call type::collateral account
+    OracleMessageNotFound,
+    CollateralAmountExceeded,
+
+    // call type::liquidation
+    LiquidationNotAllowed,
+    AccountNotLiquidatable,
+    OracleRatioNotFound,
+
+    // call type::collateral shares
+    InsufficientShareAmount,
+
+    // call type::batch
+    InvalidBatchCall,
+
+    // call type::mint commitment
+    CommitmentSignatureInvalid,
+
+    // call type::batch payment
+    BatchPaymentReused,
+
+    // call type::nft allowlist
+    AllowlistInputNotP2wpkh,
+
+    // transfer::nft royalty
+    SalePriceNotFound,
+
+    // transfer::nft
+    NftTokenNotFound,
+
+    // rule set
+    CallTypeNotActivated,
 }
@@ -58,7 +58,7 @@ async fn helper_get_address_balance(
     State(state): State<APIState>,
     Path(address): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database.clone(), true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
 
     match updater.get_address_balance(address).await {
         Ok(balance) => {
@@ -74,8 +74,10 @@ async fn helper_get_address_balance(
                 contract_infos.insert(contract_id.clone(), contract_info);
             }
 
+            let block_height = updater.get_last_indexed_block().await;
+
             return Ok(Json(
-                json!({ "balance": balance, "contract_info": contract_infos }),
+                json!({ "balance": balance, "contract_info": contract_infos, "block_height": block_height }),
             ));
         }
         Err(_) => Err(StatusCode::NOT_FOUND),
@@ -86,7 +88,7 @@ async fn helper_get_address_balance_summary(
     State(state): State<APIState>,
     Path(address): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database.clone(), true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
 
     match updater.get_address_balance(address).await {
         Ok(balance) => {
@@ -126,7 +128,7 @@ async fn helper_get_address_valid_outputs(
     State(state): State<APIState>,
     Path(address): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database.clone(), true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
 
     match updater.get_address_balance(address.clone()).await {
         Ok(balance) => {
@@ -176,7 +178,7 @@ async fn helper_get_assets_in_outpoint(
     State(state): State<APIState>,
     Path(outpoint): Path<String>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database, true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
 
     let outpoint_parts: Vec<&str> = outpoint.split(":").collect::<Vec<&str>>();
     let outpoint = OutPoint {
@@ -274,7 +276,7 @@ async fn helper_get_assets_in_outpoint(
 async fn expensive_helper_get_assets(
     State(state): State<APIState>,
 ) -> Result<Json<Value>, StatusCode> {
-    let updater = Updater::new(state.database, true).await;
+    let updater = Updater::new(state.snapshot(), true).await;
 
     let all_ticker = updater.expensive_get_all_messages().await;
 
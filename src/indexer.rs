@@ -1,29 +1,49 @@
 use super::*;
 
-use bitcoin::{consensus::deserialize, OutPoint, Transaction};
+use bitcoin::{consensus::deserialize, Block, OutPoint, Transaction};
 use bitcoincore_rpc::{Auth, Client, RpcApi};
-use constants::first_glittr_height;
-use std::{error::Error, time::Duration};
+use constants::{first_glittr_height, rules};
+use std::{
+    error::Error,
+    time::{Duration, Instant},
+};
 use store::database::INDEXER_LAST_BLOCK_PREFIX;
-use tokio::time::sleep;
+use tokio::{sync::Notify, time::timeout};
 use transaction::message::OpReturnMessage;
 
+const DEFAULT_PREFETCH_BLOCKS: usize = 16;
+const SYNC_PROGRESS_INTERVAL: Duration = Duration::from_secs(10);
+
 pub struct Indexer {
     rpc: Client,
+    pub block_source: Arc<dyn BlockSource>,
     pub database: Arc<Mutex<Database>>,
     pub last_indexed_block: Option<u64>,
+    /// Number of recent blocks that keep their undo log, None keeps every undo log.
+    pub undo_log_retention: Option<u64>,
+    pub mempool: Arc<Mutex<Mempool>>,
+    /// Notified on new blocks and transactions to skip the polling interval.
+    pub notify: Arc<Notify>,
+    /// Number of blocks fetched ahead while far behind the tip, 0 fetches one block at a time.
+    pub prefetch_blocks: usize,
+    /// Consensus rule sets by activation height, the ones of the network by default.
+    pub rules: Rules,
 }
 
 impl Indexer {
+    /// The bitcoin RPC serves the mempool, the blocks are read from `block_source`.
     pub async fn new(
         database: Arc<Mutex<Database>>,
+        block_source: Arc<dyn BlockSource>,
+        mempool: Arc<Mutex<Mempool>>,
+        notify: Arc<Notify>,
         btc_rpc_url: String,
         btc_rpc_username: String,
         btc_rpc_password: String,
     ) -> Result<Self, Box<dyn Error>> {
         let rpc = Client::new(
             btc_rpc_url.as_str(),
-            Auth::UserPass(btc_rpc_username.clone(), btc_rpc_password.clone()),
+            Auth::UserPass(btc_rpc_username, btc_rpc_password),
         )?;
 
         let mut last_indexed_block: Option<u64> = database
@@ -37,8 +57,14 @@ impl Indexer {
 
         Ok(Indexer {
             last_indexed_block,
+            undo_log_retention: CONFIG.undo_log_retention,
+            mempool,
+            notify,
+            prefetch_blocks: CONFIG.prefetch_blocks.unwrap_or(DEFAULT_PREFETCH_BLOCKS),
+            rules: rules(),
             database,
             rpc,
+            block_source,
         })
     }
 
@@ -47,15 +73,18 @@ impl Indexer {
         shutdown_signal: Arc<Mutex<bool>>,
     ) -> Result<(), Box<dyn Error>> {
         let mut updater = Updater::new(self.database.clone(), false).await;
+        updater.set_rules(self.rules.clone());
 
         log::info!("Indexing start");
-        loop {
+        'indexer: loop {
             if *shutdown_signal.lock().await {
                 log::warn!("Shutdown signal received, stopping indexer...");
                 return Ok(());
             }
 
-            let current_block_tip = self.rpc.get_block_count()?;
+            let current_block_tip = self.block_source.get_block_count()?;
+
+            self.handle_reorg().await?;
 
             let first_block_height = first_glittr_height();
             if current_block_tip < first_block_height {
@@ -63,6 +92,34 @@ impl Indexer {
                 continue;
             }
 
+            // far behind the tip (initial sync), fetch and parse the next blocks on worker tasks
+            // and skip the transactions that can't touch any glittr state
+            let sync_start_height = match self.last_indexed_block {
+                Some(value) => value + 1,
+                None => 0,
+            };
+            let mut prefetcher = None;
+            if self.prefetch_blocks > 0
+                && current_block_tip >= sync_start_height
+                && current_block_tip - sync_start_height >= self.prefetch_blocks as u64
+            {
+                log::info!(
+                    "Syncing blocks {} to {}, prefetching {} blocks",
+                    sync_start_height,
+                    current_block_tip,
+                    self.prefetch_blocks
+                );
+                prefetcher = Some(BlockPrefetcher::start(
+                    Arc::clone(&self.block_source),
+                    sync_start_height,
+                    current_block_tip,
+                    self.prefetch_blocks,
+                ));
+                updater.track_glittr_outpoints().await?;
+            }
+            let sync_started_at = Instant::now();
+            let mut progress_logged_at = sync_started_at;
+
             while self.last_indexed_block.is_none()
                 || self.last_indexed_block.unwrap() < current_block_tip
             {
@@ -71,36 +128,222 @@ impl Indexer {
                     None => 0,
                 };
 
-                let block_hash = self.rpc.get_block_hash(block_height)?;
-                let block = self.rpc.get_block(&block_hash)?;
+                let (block_hash, block, messages) = match prefetcher.as_mut() {
+                    Some(prefetcher) => {
+                        let prefetched = prefetcher
+                            .next()
+                            .await
+                            .ok_or("Block prefetcher stopped")??;
+                        (prefetched.hash, prefetched.block, prefetched.messages)
+                    }
+                    None => {
+                        let block_hash = self.block_source.get_block_hash(block_height)?;
+                        let block = self.block_source.get_block(&block_hash)?;
+                        let messages = block.txdata.iter().map(OpReturnMessage::parse_tx).collect();
+                        (block_hash, block, messages)
+                    }
+                };
+
+                if let Some(last_indexed_block) = self.last_indexed_block {
+                    let last_block_hash = self
+                        .database
+                        .lock()
+                        .await
+                        .get_block_hash(last_indexed_block);
+                    if let Some(last_block_hash) = last_block_hash {
+                        if block.header.prev_blockhash.to_string() != last_block_hash {
+                            // the chain changed while we were indexing, re-check from the tip
+                            log::warn!(
+                                "Block {} does not extend indexed block {}",
+                                block_hash,
+                                last_block_hash
+                            );
+                            updater.untrack_glittr_outpoints();
+                            continue 'indexer;
+                        }
+                    }
+                }
+
                 log::info!("Indexing block {}: {}", block_height, block_hash);
 
-                for (pos, tx) in block.txdata.iter().enumerate() {
-                    let message = OpReturnMessage::parse_tx(tx);
+                {
+                    let mut database = self.database.lock().await;
+                    database.begin_batch();
+                    database.begin_journal();
+                }
 
-                    updater.unallocate_inputs(tx).await?;
+                let result = self
+                    .index_block(&mut updater, block_height, &block, messages)
+                    .await
+                    .map_err(|error| error.to_string());
+                if let Err(error) = result {
+                    let mut database = self.database.lock().await;
+                    database.finish_journal();
+                    database.discard_batch();
+                    return Err(error.into());
+                }
 
-                    if !matches!(message.as_ref(), Err(Flaw::NonGlittrMessage)) {
-                        updater.index(block_height, pos as u32, tx, message).await?;
+                // the block state, its undo log and the last block marker are written at once
+                let mut database = self.database.lock().await;
+                let undo_log = database.finish_journal();
+                database.put_block_undo(block_height, block_hash.to_string(), undo_log);
+                if let Some(retention) = self.undo_log_retention {
+                    if block_height >= retention {
+                        database.prune_block_undo(block_height - retention);
                     }
-
-                    updater.commit_outputs(tx).await?;
                 }
+                database.put(INDEXER_LAST_BLOCK_PREFIX, "", block_height);
+                database.commit_batch()?;
+                drop(database);
 
                 self.last_indexed_block = Some(block_height);
-                self.database.lock().await.put(
-                    INDEXER_LAST_BLOCK_PREFIX,
-                    "",
-                    self.last_indexed_block,
-                );
 
                 self.database.lock().await.db.flush()?;
+
+                if prefetcher.is_some() && progress_logged_at.elapsed() >= SYNC_PROGRESS_INTERVAL {
+                    let blocks_per_sec = (block_height + 1 - sync_start_height) as f64
+                        / sync_started_at.elapsed().as_secs_f64();
+                    log::info!(
+                        "Synced block {}/{}, {:.1} blocks/s, ETA {:.0}s",
+                        block_height,
+                        current_block_tip,
+                        blocks_per_sec,
+                        (current_block_tip - block_height) as f64 / blocks_per_sec
+                    );
+                    progress_logged_at = Instant::now();
+                }
+            }
+            updater.untrack_glittr_outpoints();
+
+            // the mempool is only available through the bitcoin RPC
+            let overlay = Database::overlay(&self.database.lock().await.db);
+            let mempool_result = Mempool::update(
+                &self.mempool,
+                &self.rpc,
+                Arc::new(Mutex::new(overlay)),
+                current_block_tip + 1,
+            )
+            .await
+            .map_err(|error| error.to_string());
+            if let Err(error) = mempool_result {
+                log::warn!("Mempool update failed: {}", error);
             }
 
-            sleep(Duration::from_secs(10)).await;
+            // fall back to polling when there are no notifications
+            let _ = timeout(Duration::from_secs(10), self.notify.notified()).await;
         }
     }
 
+    async fn index_block(
+        &self,
+        updater: &mut Updater,
+        block_height: u64,
+        block: &Block,
+        messages: Vec<Result<OpReturnMessage, Flaw>>,
+    ) -> Result<(), Box<dyn Error>> {
+        for (pos, (tx, message)) in block.txdata.iter().zip(messages).enumerate() {
+            if matches!(message, Err(Flaw::NonGlittrMessage))
+                && !updater.may_spend_glittr_outpoint(tx)
+            {
+                continue;
+            }
+
+            updater.unallocate_inputs(tx).await?;
+
+            if !matches!(message.as_ref(), Err(Flaw::NonGlittrMessage)) {
+                updater.index(block_height, pos as u32, tx, message).await?;
+            }
+
+            updater.commit_outputs(tx).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Roll back indexed blocks that are no longer part of the best chain.
+    async fn handle_reorg(&mut self) -> Result<(), Box<dyn Error>> {
+        let Some(mut block_height) = self.last_indexed_block else {
+            return Ok(());
+        };
+
+        // a failing source is not a reorg, only a different hash or a shorter chain is
+        let chain_tip = self.block_source.get_block_count()?;
+
+        let mut rolled_back = false;
+        self.database.lock().await.begin_batch();
+        loop {
+            // blocks indexed before the hash was recorded can't be checked
+            let Some(indexed_hash) = self.database.lock().await.get_block_hash(block_height) else {
+                break;
+            };
+
+            if block_height <= chain_tip {
+                let chain_hash = self
+                    .block_source
+                    .get_block_hash(block_height)
+                    .map_err(|error| error.to_string());
+                let chain_hash = match chain_hash {
+                    Ok(chain_hash) => chain_hash,
+                    Err(error) => {
+                        self.database.lock().await.discard_batch();
+                        return Err(error.into());
+                    }
+                };
+                if chain_hash.to_string() == indexed_hash {
+                    break;
+                }
+            }
+
+            log::warn!(
+                "Reorg detected, rolling back block {}: {}",
+                block_height,
+                indexed_hash
+            );
+
+            if self
+                .database
+                .lock()
+                .await
+                .rollback_block(block_height)
+                .is_err()
+            {
+                self.database.lock().await.discard_batch();
+                return Err(format!(
+                    "Undo log for block {} not found, the reorg is deeper than the undo log \
+                     retention and the index must be resynced",
+                    block_height
+                )
+                .into());
+            }
+            rolled_back = true;
+
+            if block_height == 0 {
+                self.last_indexed_block = None;
+                break;
+            }
+            block_height -= 1;
+            self.last_indexed_block = Some(block_height);
+        }
+
+        if rolled_back {
+            let mut database = self.database.lock().await;
+            match self.last_indexed_block {
+                Some(last_indexed_block) => {
+                    database.put(INDEXER_LAST_BLOCK_PREFIX, "", last_indexed_block)
+                }
+                None => database.delete(INDEXER_LAST_BLOCK_PREFIX, ""),
+            }
+            database.commit_batch()?;
+            database.db.flush()?;
+
+            log::info!("Rolled back to block {:?}", self.last_indexed_block);
+        } else {
+            self.database.lock().await.discard_batch();
+        }
+
+        Ok(())
+    }
+
     pub async fn get_script_pubkey_from_outpoint(
         &self,
         outpoint: OutPoint,
@@ -1,32 +1,85 @@
 #![allow(irrefutable_let_patterns)]
 
+use borsh::{BorshDeserialize, BorshSerialize};
 use config::CONFIG;
 use serde::{Deserialize, Serialize};
 use std::{env, error::Error, process::exit, sync::Arc};
 use store::database::Database;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Notify};
 
 mod api;
+mod block_source;
 mod config;
 mod constants;
 mod flaw;
 mod indexer;
 mod macros;
+mod mempool;
 mod store;
 mod transaction;
 mod types;
 mod updater;
+mod zmq;
 
 #[cfg(feature = "helper-api")]
 mod helper_api;
 
 pub use api::*;
+pub use block_source::*;
+pub use constants::{RuleSet, Rules};
 pub use flaw::*;
 pub use indexer::*;
+pub use mempool::*;
 pub use store::*;
 pub use transaction::*;
 pub use types::*;
 pub use updater::*;
+pub use zmq::*;
+
+/// Rewind the index to `to_height`, the indexer must not be running.
+pub fn rollback(to_height: u64) -> Result<(), Box<dyn Error>> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info")
+    }
+    env_logger::init();
+
+    log::info!("Rolling back to block {}", to_height);
+
+    let mut database = Database::new(CONFIG.rocks_db_path.clone());
+    database.check_schema_version()?;
+    database.rollback_to(to_height).map_err(|error| {
+        format!(
+            "Rollback to block {} failed, the height must be below the last indexed block and \
+             the undo logs of the later blocks must be kept: {:?}",
+            to_height, error
+        )
+    })?;
+    database.db.flush()?;
+
+    log::info!("Rolled back to block {}", to_height);
+
+    Ok(())
+}
+
+/// Upgrade the database to the current schema version, the indexer must not be running.
+pub fn migrate() -> Result<(), Box<dyn Error>> {
+    if env::var("RUST_LOG").is_err() {
+        env::set_var("RUST_LOG", "info")
+    }
+    env_logger::init();
+
+    let database = Database::new(CONFIG.rocks_db_path.clone());
+    log::info!(
+        "Database schema version {}, current version {}",
+        database.schema_version()?,
+        SCHEMA_VERSION
+    );
+    database.migrate()?;
+
+    log::info!("Database migrated to schema version {}", SCHEMA_VERSION);
+
+    Ok(())
+}
 
 #[tokio::main]
 pub async fn run() -> Result<(), Box<dyn Error>> {
@@ -48,8 +101,20 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
         }
     }
 
-    let database = Arc::new(Mutex::new(Database::new(CONFIG.rocks_db_path.clone())));
-    let database_indexer = Arc::clone(&database);
+    let database = Database::new(CONFIG.rocks_db_path.clone());
+    database.check_schema_version()?;
+
+    // the indexer is the only writer, the API reads from snapshots
+    let db = Arc::clone(&database.db);
+    let database_indexer = Arc::new(Mutex::new(database));
+    let mempool = Arc::new(Mutex::new(Mempool::default()));
+    let mempool_indexer = Arc::clone(&mempool);
+    let notify = Arc::new(Notify::new());
+    let notify_indexer = Arc::clone(&notify);
+
+    if let Some(zmq_url) = CONFIG.zmq_url.clone() {
+        tokio::spawn(run_zmq_subscriber(zmq_url, notify));
+    }
 
     // Add Ctrl+C handling
     let shutdown_signal = Arc::new(Mutex::new(false));
@@ -68,6 +133,9 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
     let indexer_handle = tokio::spawn(async {
         let mut current_indexer = Indexer::new(
             database_indexer,
+            new_block_source().expect("Block source").into(),
+            mempool_indexer,
+            notify_indexer,
             CONFIG.btc_rpc_url.clone(),
             CONFIG.btc_rpc_username.clone(),
             CONFIG.btc_rpc_password.clone(),
@@ -90,7 +158,7 @@ pub async fn run() -> Result<(), Box<dyn Error>> {
     });
 
     let api_handle = tokio::spawn(async {
-        run_api(database)
+        run_api(db, mempool)
             .await
             .expect("Run API")
     });
@@ -0,0 +1,363 @@
+use super::*;
+
+use bitcoin::{OutPoint, Transaction, Txid};
+use bitcoincore_rpc::{Client, RpcApi};
+use std::collections::{HashMap, HashSet, VecDeque};
+use transaction::message::OpReturnMessage;
+
+/// Outcome of an unconfirmed transaction, evaluated on top of the confirmed state and the
+/// unconfirmed transactions before it as if they were all mined in the next block.
+#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct PendingTransaction {
+    pub outcome: Option<MessageDataOutcome>,
+    pub assets: HashMap<u32, AssetList>,
+}
+
+#[derive(Default)]
+pub struct Mempool {
+    transactions: HashMap<Txid, Transaction>,
+    pending: HashMap<Txid, PendingTransaction>,
+}
+
+impl Mempool {
+    pub fn get(&self, txid: &Txid) -> Option<&PendingTransaction> {
+        self.pending.get(txid)
+    }
+
+    pub fn get_asset_list(&self, outpoint: &OutPoint) -> Option<&AssetList> {
+        self.pending
+            .get(&outpoint.txid)
+            .and_then(|pending| pending.assets.get(&outpoint.vout))
+    }
+
+    /// Re-evaluate the unconfirmed transactions on an overlay of the confirmed state, see
+    /// `Database::overlay`, transactions that are mined, replaced or dropped from the mempool
+    /// are evicted. The lock is only held to swap the state, the API serves the previous
+    /// outcomes while the RPC calls and the evaluation run.
+    pub async fn update(
+        mempool: &Mutex<Mempool>,
+        rpc: &Client,
+        database: Arc<Mutex<Database>>,
+        block_height: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        // the indexer is the only one updating the mempool
+        let mut transactions = std::mem::take(&mut mempool.lock().await.transactions);
+        let pending = evaluate(&mut transactions, rpc, database, block_height)
+            .await
+            .map_err(|error| error.to_string());
+
+        let mut mempool = mempool.lock().await;
+        mempool.transactions = transactions;
+        mempool.pending = pending?;
+
+        Ok(())
+    }
+}
+
+async fn evaluate(
+    transactions: &mut HashMap<Txid, Transaction>,
+    rpc: &Client,
+    database: Arc<Mutex<Database>>,
+    block_height: u64,
+) -> Result<HashMap<Txid, PendingTransaction>, Box<dyn Error>> {
+    let txids: HashSet<Txid> = rpc.get_raw_mempool()?.into_iter().collect();
+
+    transactions.retain(|txid, _| txids.contains(txid));
+    for txid in txids.iter() {
+        if !transactions.contains_key(txid) {
+            // the transaction could leave the mempool in the meantime
+            if let Ok(tx) = rpc.get_raw_transaction(txid, None) {
+                transactions.insert(*txid, tx);
+            }
+        }
+    }
+
+    evaluate_transactions(transactions.values().collect(), database, block_height).await
+}
+
+/// Index the transactions in dependency order, the writes of a transaction are seen by the
+/// transactions spending from it and never reach the confirmed state as long as `database`
+/// is an overlay.
+async fn evaluate_transactions(
+    transactions: Vec<&Transaction>,
+    database: Arc<Mutex<Database>>,
+    block_height: u64,
+) -> Result<HashMap<Txid, PendingTransaction>, Box<dyn Error>> {
+    let mut updater = Updater::new(database, false).await;
+    let mut pending = HashMap::new();
+
+    for (pos, tx) in sort_by_dependency(transactions).into_iter().enumerate() {
+        let txid = tx.compute_txid();
+        let message = OpReturnMessage::parse_tx(tx);
+
+        updater.unallocate_inputs(tx).await?;
+
+        let mut outcome = None;
+        if !matches!(message.as_ref(), Err(Flaw::NonGlittrMessage)) {
+            outcome = Some(
+                updater
+                    .index(block_height, pos as u32 + 1, tx, message)
+                    .await?,
+            );
+        }
+
+        updater.commit_outputs(tx).await?;
+
+        let mut assets = HashMap::new();
+        for vout in 0..tx.output.len() as u32 {
+            if let Ok(asset_list) = updater.get_asset_list(&OutPoint { txid, vout }).await {
+                if !asset_list.list.is_empty() {
+                    assets.insert(vout, asset_list);
+                }
+            }
+        }
+
+        if outcome.is_some() || !assets.is_empty() {
+            pending.insert(txid, PendingTransaction { outcome, assets });
+        }
+    }
+
+    Ok(pending)
+}
+
+/// Order the transactions so that every transaction comes after the unconfirmed
+/// transactions it spends from.
+fn sort_by_dependency(transactions: Vec<&Transaction>) -> Vec<&Transaction> {
+    let by_txid: HashMap<Txid, &Transaction> = transactions
+        .iter()
+        .map(|tx| (tx.compute_txid(), *tx))
+        .collect();
+
+    let mut parent_count: HashMap<Txid, usize> = HashMap::new();
+    let mut children: HashMap<Txid, Vec<Txid>> = HashMap::new();
+    for (txid, tx) in by_txid.iter() {
+        let parents: HashSet<Txid> = tx
+            .input
+            .iter()
+            .map(|input| input.previous_output.txid)
+            .filter(|parent| by_txid.contains_key(parent))
+            .collect();
+
+        parent_count.insert(*txid, parents.len());
+        for parent in parents {
+            children.entry(parent).or_default().push(*txid);
+        }
+    }
+
+    let mut ready: Vec<Txid> = parent_count
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(txid, _)| *txid)
+        .collect();
+    ready.sort();
+    let mut queue: VecDeque<Txid> = ready.into();
+
+    let mut sorted = Vec::new();
+    while let Some(txid) = queue.pop_front() {
+        sorted.push(by_txid[&txid]);
+
+        for child in children.remove(&txid).unwrap_or_default() {
+            let count = parent_count.get_mut(&child).unwrap();
+            *count -= 1;
+            if *count == 0 {
+                queue.push_back(child);
+            }
+        }
+    }
+
+    sorted
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::{absolute::LockTime, transaction::Version, Amount, ScriptBuf, TxIn, TxOut};
+    use database::{ASSET_CONTRACT_DATA_PREFIX, ASSET_LIST_PREFIX};
+    use message::{CallType, ContractCall, ContractCreation, ContractType, MintBurnOption};
+    use mint_only_asset::{MOAMintMechanisms, MintOnlyAssetContract};
+    use transaction_shared::FreeMint;
+
+    use super::*;
+
+    fn spending(previous_outputs: Vec<OutPoint>, value: u64) -> Transaction {
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: previous_outputs
+                .into_iter()
+                .map(|previous_output| TxIn {
+                    previous_output,
+                    ..Default::default()
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: Amount::from_sat(value),
+                script_pubkey: ScriptBuf::new(),
+            }],
+        }
+    }
+
+    #[test]
+    fn sort_by_dependency_parents_first() {
+        let parent = spending(vec![OutPoint::null()], 1);
+        let child = spending(
+            vec![OutPoint {
+                txid: parent.compute_txid(),
+                vout: 0,
+            }],
+            2,
+        );
+        let grandchild = spending(
+            vec![
+                OutPoint {
+                    txid: child.compute_txid(),
+                    vout: 0,
+                },
+                OutPoint {
+                    txid: parent.compute_txid(),
+                    vout: 1,
+                },
+            ],
+            3,
+        );
+
+        let sorted = sort_by_dependency(vec![&grandchild, &child, &parent]);
+        let sorted: Vec<Txid> = sorted.iter().map(|tx| tx.compute_txid()).collect();
+
+        assert_eq!(
+            sorted,
+            vec![
+                parent.compute_txid(),
+                child.compute_txid(),
+                grandchild.compute_txid()
+            ]
+        );
+    }
+
+    fn with_message(mut tx: Transaction, message: &OpReturnMessage) -> Transaction {
+        tx.output.insert(
+            0,
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: message.into_script(),
+            },
+        );
+        tx
+    }
+
+    #[tokio::test]
+    async fn evaluate_chained_transactions_on_pending_state() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let database = Arc::new(Mutex::new(Database::new(
+            tempdir.path().to_str().unwrap().to_string(),
+        )));
+
+        // a single free mint fits in the supply cap
+        let contract = BlockTx { block: 1, tx: 0 };
+        let contract_tx = with_message(
+            spending(vec![OutPoint::null()], 1),
+            &OpReturnMessage {
+                contract_creation: Some(ContractCreation {
+                    spec: None,
+                    contract_type: ContractType::Moa(MintOnlyAssetContract {
+                        ticker: None,
+                        supply_cap: Some(U128(10)),
+                        divisibility: 0,
+                        live_time: 0,
+                        end_time: None,
+                        mint_mechanism: MOAMintMechanisms {
+                            preallocated: None,
+                            free_mint: Some(FreeMint {
+                                supply_cap: Some(U128(10)),
+                                amount_per_mint: U128(10),
+                            }),
+                            purchase: None,
+                        },
+                        commitment: None,
+                    }),
+                }),
+                transfer: None,
+                contract_call: None,
+            },
+        );
+        let mut updater = Updater::new(Arc::clone(&database), false).await;
+        let outcome = updater
+            .index(
+                contract.block,
+                contract.tx,
+                &contract_tx,
+                OpReturnMessage::parse_tx(&contract_tx),
+            )
+            .await
+            .unwrap();
+        assert!(outcome.flaw.is_none());
+
+        let mint_message = OpReturnMessage {
+            contract_creation: None,
+            transfer: None,
+            contract_call: Some(ContractCall {
+                contract: Some(contract.to_tuple()),
+                call_type: CallType::Mint(MintBurnOption {
+                    pointer: Some(1),
+                    oracle_message: None,
+                    pointer_to_key: None,
+                    assert_values: None,
+                    commitment_message: None,
+                }),
+            }),
+        };
+        let parent = with_message(
+            spending(vec![contract_tx.input[0].previous_output], 2),
+            &mint_message,
+        );
+        let child = with_message(
+            spending(
+                vec![OutPoint {
+                    txid: parent.compute_txid(),
+                    vout: 1,
+                }],
+                3,
+            ),
+            &mint_message,
+        );
+
+        let overlay = Arc::new(Mutex::new(Database::overlay(&database.lock().await.db)));
+        let pending = evaluate_transactions(vec![&child, &parent], overlay, 2)
+            .await
+            .unwrap();
+
+        let parent_pending = &pending[&parent.compute_txid()];
+        assert!(parent_pending.outcome.as_ref().unwrap().flaw.is_none());
+        assert_eq!(
+            parent_pending.assets[&1].list.get(&contract.to_string()),
+            Some(&10)
+        );
+
+        // the child sees the supply minted by its parent and keeps the spent assets
+        let child_pending = &pending[&child.compute_txid()];
+        assert_eq!(
+            child_pending.outcome.as_ref().unwrap().flaw,
+            Some(Flaw::SupplyCapExceeded)
+        );
+        assert_eq!(
+            child_pending.assets[&1].list.get(&contract.to_string()),
+            Some(&10)
+        );
+
+        // the confirmed state is left untouched
+        let database = database.lock().await;
+        assert!(database
+            .get::<AssetContractData>(ASSET_CONTRACT_DATA_PREFIX, &contract.to_string())
+            .is_err());
+        assert!(database
+            .get::<AssetList>(
+                ASSET_LIST_PREFIX,
+                &OutPoint {
+                    txid: parent.compute_txid(),
+                    vout: 1
+                }
+                .to_string()
+            )
+            .is_err());
+    }
+}
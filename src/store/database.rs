@@ -1,8 +1,15 @@
 use super::*;
-use bitcoincore_rpc::jsonrpc::serde_json::{self, Deserializer};
-use rocksdb::{IteratorMode, DB};
+use bitcoin::{hashes::Hash, OutPoint, Txid};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, IteratorMode, Snapshot, WriteBatch, DB};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+};
 
+// every prefix is stored in its own column family
 pub const INDEXER_LAST_BLOCK_PREFIX: &str = "last_block";
+pub const BLOCK_HASH_PREFIX: &str = "block_hash";
+pub const UNDO_LOG_PREFIX: &str = "undo_log";
 pub const MESSAGE_PREFIX: &str = "message";
 pub const TRANSACTION_TO_BLOCK_TX_PREFIX: &str = "tx_to_blocktx";
 pub const TICKER_TO_BLOCK_TX_PREFIX: &str = "ticker_to_blocktx";
@@ -14,18 +21,140 @@ pub const COLLATERAL_ACCOUNTS_PREFIX: &str = "collateral_account";
 pub const COLLATERALIZED_CONTRACT_DATA: &str = "pool_data";
 pub const STATE_KEYS_PREFIX: &str = "state_key";
 pub const SPEC_CONTRACT_OWNED_PREFIX: &str = "spec_contract_owned";
+pub const COMMITMENT_PREFIX: &str = "commitment";
+pub const NFT_CONTRACT_DATA_PREFIX: &str = "nft_contract_data";
+pub const NFT_MINT_COUNT_PREFIX: &str = "nft_mint_count";
+pub const NFT_TOKEN_PREFIX: &str = "nft_token";
+pub const NFT_TOKENS_PREFIX: &str = "nft_tokens";
+pub const CONTRACT_COLLATERAL_ACCOUNTS_PREFIX: &str = "contract_collateral_accounts";
 
-#[cfg(feature = "helper-api")]
+// the helper-api column families are opened without the feature too, rocksdb refuses to open
+// a database without every existing column family
 pub const ADDRESS_ASSET_LIST_PREFIX: &str = "address_asset_list";
-
-#[cfg(feature = "helper-api")]
 pub const TXID_TO_TRANSACTION_PREFIX: &str = "txid_to_transaction";
-
-#[cfg(feature = "helper-api")]
 pub const OUTPOINT_TO_ADDRESS: &str = "outpoint_to_address";
 
+/// How the string keys of a column family are stored. Numbers are big endian so the keys are
+/// iterated in numeric order.
+#[derive(Clone, Copy)]
+enum KeyEncoding {
+    Text,
+    Height,
+    BlockTx,
+    Txid,
+    OutPoint,
+}
+
+const COLUMN_FAMILIES: [(&str, KeyEncoding); 22] = [
+    (INDEXER_LAST_BLOCK_PREFIX, KeyEncoding::Text),
+    (BLOCK_HASH_PREFIX, KeyEncoding::Height),
+    (UNDO_LOG_PREFIX, KeyEncoding::Height),
+    (MESSAGE_PREFIX, KeyEncoding::BlockTx),
+    (TRANSACTION_TO_BLOCK_TX_PREFIX, KeyEncoding::Txid),
+    (TICKER_TO_BLOCK_TX_PREFIX, KeyEncoding::Text),
+    (ASSET_LIST_PREFIX, KeyEncoding::OutPoint),
+    (ASSET_CONTRACT_DATA_PREFIX, KeyEncoding::BlockTx),
+    (VESTING_CONTRACT_DATA_PREFIX, KeyEncoding::BlockTx),
+    (COLLATERAL_ACCOUNTS_PREFIX, KeyEncoding::OutPoint),
+    (COLLATERALIZED_CONTRACT_DATA, KeyEncoding::BlockTx),
+    (STATE_KEYS_PREFIX, KeyEncoding::OutPoint),
+    (SPEC_CONTRACT_OWNED_PREFIX, KeyEncoding::OutPoint),
+    (COMMITMENT_PREFIX, KeyEncoding::Text),
+    (NFT_CONTRACT_DATA_PREFIX, KeyEncoding::BlockTx),
+    (NFT_MINT_COUNT_PREFIX, KeyEncoding::Text),
+    (NFT_TOKEN_PREFIX, KeyEncoding::Text),
+    (NFT_TOKENS_PREFIX, KeyEncoding::OutPoint),
+    (CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, KeyEncoding::BlockTx),
+    (ADDRESS_ASSET_LIST_PREFIX, KeyEncoding::Text),
+    (TXID_TO_TRANSACTION_PREFIX, KeyEncoding::Txid),
+    (OUTPOINT_TO_ADDRESS, KeyEncoding::OutPoint),
+];
+
+impl KeyEncoding {
+    fn encode(self, key: &str) -> Option<Vec<u8>> {
+        match self {
+            KeyEncoding::Text => Some(key.as_bytes().to_vec()),
+            KeyEncoding::Height => Some(key.parse::<u64>().ok()?.to_be_bytes().to_vec()),
+            KeyEncoding::BlockTx => {
+                let (block, tx) = key.split_once(':')?;
+                let mut bytes = block.parse::<u64>().ok()?.to_be_bytes().to_vec();
+                bytes.extend_from_slice(&tx.parse::<u32>().ok()?.to_be_bytes());
+                Some(bytes)
+            }
+            KeyEncoding::Txid => Some(Txid::from_str(key).ok()?.to_byte_array().to_vec()),
+            KeyEncoding::OutPoint => {
+                let outpoint = OutPoint::from_str(key).ok()?;
+                let mut bytes = outpoint.txid.to_byte_array().to_vec();
+                bytes.extend_from_slice(&outpoint.vout.to_be_bytes());
+                Some(bytes)
+            }
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            KeyEncoding::Text => String::from_utf8(bytes.to_vec()).ok(),
+            KeyEncoding::Height => Some(u64::from_be_bytes(bytes.try_into().ok()?).to_string()),
+            KeyEncoding::BlockTx => {
+                if bytes.len() != 12 {
+                    return None;
+                }
+                Some(
+                    BlockTx {
+                        block: u64::from_be_bytes(bytes[..8].try_into().ok()?),
+                        tx: u32::from_be_bytes(bytes[8..].try_into().ok()?),
+                    }
+                    .to_string(),
+                )
+            }
+            KeyEncoding::Txid => Some(Txid::from_slice(bytes).ok()?.to_string()),
+            KeyEncoding::OutPoint => {
+                if bytes.len() != 36 {
+                    return None;
+                }
+                Some(
+                    OutPoint {
+                        txid: Txid::from_slice(&bytes[..32]).ok()?,
+                        vout: u32::from_be_bytes(bytes[32..].try_into().ok()?),
+                    }
+                    .to_string(),
+                )
+            }
+        }
+    }
+}
+
+fn column(prefix: &str) -> (&'static str, KeyEncoding) {
+    *COLUMN_FAMILIES
+        .iter()
+        .find(|(name, _)| *name == prefix)
+        .unwrap_or_else(|| panic!("Unknown column family {}", prefix))
+}
+
+/// Column family and binary key of a `prefix`, `key` pair, None when the key is malformed.
+pub(super) fn column_key(prefix: &str, key: &str) -> Option<ColumnKey> {
+    let (name, encoding) = column(prefix);
+    Some((name, encoding.encode(key)?))
+}
+
+pub(super) type ColumnKey = (&'static str, Vec<u8>);
+
+self_cell::self_cell!(
+    /// Snapshot that owns a handle to its database, so the database outlives the snapshot.
+    struct DatabaseSnapshot {
+        owner: Arc<DB>,
+
+        #[covariant]
+        dependent: Snapshot,
+    }
+);
+
 pub struct Database {
+    snapshot: Option<DatabaseSnapshot>,
     pub db: Arc<DB>,
+    journal: Option<Journal>,
+    batch: Option<Batch>,
+    savepoint: Option<Savepoint>,
 }
 
 #[derive(Debug)]
@@ -34,85 +163,625 @@ pub enum DatabaseError {
     DeserializeFailed,
 }
 
+/// Previous raw value of a key before it was first touched in a block,
+/// None means the key did not exist.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct UndoEntry {
+    pub column: String,
+    pub key: Vec<u8>,
+    pub previous: Option<Vec<u8>>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
+pub struct BlockUndoLog {
+    pub entries: Vec<UndoEntry>,
+}
+
+/// Pending writes of an open batch, None marks a deleted key.
+type Batch = BTreeMap<ColumnKey, Option<Vec<u8>>>;
+
+/// Previous raw value of the keys written since the savepoint began.
+type Savepoint = HashMap<ColumnKey, Option<Vec<u8>>>;
+
+#[derive(Default)]
+struct Journal {
+    touched: HashSet<ColumnKey>,
+    entries: Vec<UndoEntry>,
+}
+
 // TODO:
 // - implement error handling
-// - add transaction feature
 impl Database {
     pub fn new(path: String) -> Self {
         let mut options = rocksdb::Options::default();
         options.create_if_missing(true);
+        options.create_missing_column_families(true);
         options.set_manual_wal_flush(true);
         options.set_wal_recovery_mode(rocksdb::DBRecoveryMode::AbsoluteConsistency);
         options.set_compression_type(rocksdb::DBCompressionType::Zstd);
 
+        let column_families = COLUMN_FAMILIES.iter().map(|(name, _)| {
+            let mut column_options = rocksdb::Options::default();
+            column_options.set_compression_type(rocksdb::DBCompressionType::Zstd);
+            ColumnFamilyDescriptor::new(*name, column_options)
+        });
+
+        let database = Self {
+            snapshot: None,
+            db: Arc::new(DB::open_cf_descriptors(&options, path, column_families).unwrap()),
+            journal: None,
+            batch: None,
+            savepoint: None,
+        };
+
+        database
+            .init_schema_version()
+            .expect("Error writing the schema version");
+
+        database
+    }
+
+    /// Read-only view of the database as it is now. The indexer commits every block in a single
+    /// write batch, so the snapshot always sits on a block boundary.
+    pub fn snapshot(db: &Arc<DB>) -> Self {
+        let snapshot = DatabaseSnapshot::new(Arc::clone(db), |db| db.snapshot());
+
         Self {
-            db: Arc::new(DB::open(&options, path).unwrap()),
+            snapshot: Some(snapshot),
+            db: Arc::clone(db),
+            journal: None,
+            batch: None,
+            savepoint: None,
         }
     }
 
-    pub fn put<T: Serialize>(&mut self, prefix: &str, key: &str, value: T) {
-        self.db
-            .put(
-                format!("{}:{}", prefix, key),
-                serde_json::to_string(&value).unwrap(),
-            )
-            .expect("Error putting data into database");
+    /// Snapshot taking writes into an open batch that is never committed, the writes are only
+    /// seen by the later reads of the overlay.
+    pub fn overlay(db: &Arc<DB>) -> Self {
+        let mut overlay = Self::snapshot(db);
+        overlay.begin_batch();
+
+        overlay
     }
 
-    pub fn get<T: for<'a> Deserialize<'a>>(
-        &self,
-        prefix: &str,
-        key: &str,
-    ) -> Result<T, DatabaseError> {
-        let value = self
-            .db
-            .get(format!("{}:{}", prefix, key))
-            .expect("Error getting data from database");
+    pub fn is_snapshot(&self) -> bool {
+        self.snapshot.is_some()
+    }
 
-        if let Some(value) = value {
-            let message = T::deserialize(&mut Deserializer::from_slice(value.as_slice()));
+    /// Last indexed block height, the height a snapshot was taken at.
+    pub fn block_height(&self) -> Option<u64> {
+        self.get(INDEXER_LAST_BLOCK_PREFIX, "").ok()
+    }
 
-            return match message {
-                Ok(message) => Ok(message),
-                Err(_) => Err(DatabaseError::DeserializeFailed),
-            };
+    pub fn put<T: BorshSerialize>(&mut self, prefix: &str, key: &str, value: T) {
+        let key = column_key(prefix, key).expect("Invalid database key");
+        self.write(key, Some(borsh::to_vec(&value).unwrap()));
+    }
+
+    pub fn get<T: BorshDeserialize>(&self, prefix: &str, key: &str) -> Result<T, DatabaseError> {
+        // a malformed key can't be stored
+        let Some(key) = column_key(prefix, key) else {
+            return Err(DatabaseError::NotFound);
+        };
+
+        match self.read(&key) {
+            Some(value) => T::try_from_slice(&value).map_err(|_| DatabaseError::DeserializeFailed),
+            None => Err(DatabaseError::NotFound),
         }
-        Err(DatabaseError::NotFound)
     }
 
-    pub fn expensive_find_by_prefix<T: for<'a> Deserialize<'a>>(
+    /// Every value of the column family, keyed by `prefix:key`.
+    pub fn expensive_find_by_prefix<T: BorshDeserialize>(
         &self,
         prefix: &str,
     ) -> Result<Vec<(String, T)>, DatabaseError> {
         let mut results = Vec::new();
-        let iter = self.db.iterator(IteratorMode::From(
-            prefix.as_bytes(),
-            rocksdb::Direction::Forward,
-        ));
+        for (key, value) in self.find_all(prefix)? {
+            match T::try_from_slice(&value) {
+                Ok(deserialized) => results.push((key, deserialized)),
+                Err(_) => return Err(DatabaseError::DeserializeFailed),
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Every key of the column family as `prefix:key`, the values are not decoded.
+    pub fn expensive_find_keys_by_prefix(
+        &self,
+        prefix: &str,
+    ) -> Result<Vec<String>, DatabaseError> {
+        Ok(self
+            .find_all(prefix)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect())
+    }
+
+    fn find_all(&self, prefix: &str) -> Result<Vec<(String, Vec<u8>)>, DatabaseError> {
+        let (name, encoding) = column(prefix);
+
+        let items = match &self.snapshot {
+            Some(snapshot) => snapshot
+                .borrow_dependent()
+                .iterator_cf(self.cf(name), IteratorMode::Start),
+            None => self.db.iterator_cf(self.cf(name), IteratorMode::Start),
+        };
 
-        for item in iter {
+        let mut values: BTreeMap<Vec<u8>, Vec<u8>> = BTreeMap::new();
+        for item in items {
             match item {
                 Ok((key, value)) => {
-                    let key_str = String::from_utf8_lossy(&key);
-                    if !key_str.starts_with(prefix) {
-                        break; // Stop when we've moved past the prefix
-                    }
-
-                    match T::deserialize(&mut Deserializer::from_slice(&value)) {
-                        Ok(deserialized) => results.push((key_str.to_string(), deserialized)),
-                        Err(_) => return Err(DatabaseError::DeserializeFailed),
-                    }
+                    values.insert(key.to_vec(), value.to_vec());
                 }
                 Err(_) => return Err(DatabaseError::DeserializeFailed),
             }
         }
 
+        // pending writes of the open batch take precedence
+        if let Some(batch) = &self.batch {
+            for ((_, key), value) in batch
+                .range((name, Vec::new())..)
+                .take_while(|((column, _), _)| *column == name)
+            {
+                match value {
+                    Some(value) => values.insert(key.clone(), value.clone()),
+                    None => values.remove(key),
+                };
+            }
+        }
+
+        let mut results = Vec::new();
+        for (key, value) in values {
+            let Some(key) = encoding.decode(&key) else {
+                return Err(DatabaseError::DeserializeFailed);
+            };
+            results.push((format!("{}:{}", prefix, key), value));
+        }
+
         Ok(results)
     }
 
     pub fn delete(&mut self, prefix: &str, key: &str) {
-        self.db
-            .delete(format!("{}:{}", prefix, key))
-            .expect("Error deleting data from database");
+        let key = column_key(prefix, key).expect("Invalid database key");
+        self.write(key, None);
+    }
+
+    fn cf(&self, name: &str) -> &ColumnFamily {
+        self.db.cf_handle(name).expect("Column family not found")
+    }
+
+    fn read(&self, key: &ColumnKey) -> Option<Vec<u8>> {
+        if let Some(value) = self.batch.as_ref().and_then(|batch| batch.get(key)) {
+            return value.clone();
+        }
+
+        match &self.snapshot {
+            Some(snapshot) => snapshot.borrow_dependent().get_cf(self.cf(key.0), &key.1),
+            None => self.db.get_cf(self.cf(key.0), &key.1),
+        }
+        .expect("Error getting data from database")
+    }
+
+    fn write(&mut self, key: ColumnKey, value: Option<Vec<u8>>) {
+        // only the indexer writes, the readers go through read-only updaters or overlays
+        assert!(
+            self.snapshot.is_none() || self.batch.is_some(),
+            "Database snapshot is read-only"
+        );
+        self.record_undo(&key);
+        self.record_savepoint(&key);
+
+        if let Some(batch) = self.batch.as_mut() {
+            batch.insert(key, value);
+            return;
+        }
+
+        match value {
+            Some(value) => self
+                .db
+                .put_cf(self.cf(key.0), key.1, value)
+                .expect("Error putting data into database"),
+            None => self
+                .db
+                .delete_cf(self.cf(key.0), key.1)
+                .expect("Error deleting data from database"),
+        }
+    }
+
+    /// Buffer every write until `commit_batch` is called. Reads see the buffered writes.
+    pub fn begin_batch(&mut self) {
+        self.batch = Some(Batch::new());
+    }
+
+    /// Atomically write the buffered writes into the database.
+    pub fn commit_batch(&mut self) -> Result<(), rocksdb::Error> {
+        let Some(batch) = self.batch.take() else {
+            return Ok(());
+        };
+        assert!(self.snapshot.is_none(), "Database snapshot is read-only");
+
+        let mut write_batch = WriteBatch::default();
+        for ((name, key), value) in batch {
+            match value {
+                Some(value) => write_batch.put_cf(self.cf(name), key, value),
+                None => write_batch.delete_cf(self.cf(name), key),
+            }
+        }
+
+        self.db.write(write_batch)
+    }
+
+    pub fn discard_batch(&mut self) {
+        self.batch = None;
+    }
+
+    /// Start recording the previous value of every key written until `finish_journal` is called.
+    pub fn begin_journal(&mut self) {
+        self.journal = Some(Journal::default());
+    }
+
+    pub fn finish_journal(&mut self) -> BlockUndoLog {
+        let journal = self.journal.take().unwrap_or_default();
+        BlockUndoLog {
+            entries: journal.entries,
+        }
+    }
+
+    fn record_undo(&mut self, key: &ColumnKey) {
+        let Some(journal) = self.journal.as_ref() else {
+            return;
+        };
+
+        if journal.touched.contains(key) {
+            return;
+        }
+
+        let previous = self.read(key);
+
+        let journal = self.journal.as_mut().unwrap();
+        journal.touched.insert(key.clone());
+        journal.entries.push(UndoEntry {
+            column: key.0.to_string(),
+            key: key.1.clone(),
+            previous,
+        });
+    }
+
+    /// Record the writes from now on, `rollback_savepoint` undoes them.
+    pub fn begin_savepoint(&mut self) {
+        self.savepoint = Some(Savepoint::new());
+    }
+
+    /// Keep the writes since `begin_savepoint`.
+    pub fn release_savepoint(&mut self) {
+        self.savepoint = None;
+    }
+
+    /// Undo the writes since `begin_savepoint`.
+    pub fn rollback_savepoint(&mut self) {
+        let Some(savepoint) = self.savepoint.take() else {
+            return;
+        };
+
+        for (key, previous) in savepoint {
+            self.write(key, previous);
+        }
+    }
+
+    fn record_savepoint(&mut self, key: &ColumnKey) {
+        let Some(savepoint) = self.savepoint.as_ref() else {
+            return;
+        };
+
+        if savepoint.contains_key(key) {
+            return;
+        }
+
+        let previous = self.read(key);
+        self.savepoint
+            .as_mut()
+            .unwrap()
+            .insert(key.clone(), previous);
+    }
+
+    /// Store the undo log and hash of an indexed block.
+    pub fn put_block_undo(
+        &mut self,
+        block_height: u64,
+        block_hash: String,
+        undo_log: BlockUndoLog,
+    ) {
+        self.put(UNDO_LOG_PREFIX, &block_height.to_string(), undo_log);
+        self.put(BLOCK_HASH_PREFIX, &block_height.to_string(), block_hash);
+    }
+
+    /// Drop the undo logs of `block_height` and every older block, those blocks can't be rolled
+    /// back afterwards. Older undo logs are left over when the retention is lowered. The block
+    /// hashes are kept so a reorg deeper than the retention is still detected, the indexer stops
+    /// on it and the index must be resynced.
+    pub fn prune_block_undo(&mut self, block_height: u64) {
+        let cutoff = block_height.to_be_bytes();
+        let pruned: Vec<Vec<u8>> = self
+            .db
+            .iterator_cf(self.cf(UNDO_LOG_PREFIX), IteratorMode::Start)
+            .map(|item| item.expect("Error getting data from database").0)
+            .take_while(|key| key.as_ref() <= cutoff.as_slice())
+            .map(|key| key.to_vec())
+            .collect();
+
+        for key in pruned {
+            self.write((UNDO_LOG_PREFIX, key), None);
+        }
+    }
+
+    pub fn get_block_hash(&self, block_height: u64) -> Option<String> {
+        self.get(BLOCK_HASH_PREFIX, &block_height.to_string()).ok()
+    }
+
+    /// Revert every write made while indexing the block, restoring the values in reverse order.
+    pub fn rollback_block(&mut self, block_height: u64) -> Result<(), DatabaseError> {
+        let undo_log: BlockUndoLog = self.get(UNDO_LOG_PREFIX, &block_height.to_string())?;
+
+        for entry in undo_log.entries.into_iter().rev() {
+            let (name, _) = column(&entry.column);
+            self.write((name, entry.key), entry.previous);
+        }
+
+        self.delete(UNDO_LOG_PREFIX, &block_height.to_string());
+        self.delete(BLOCK_HASH_PREFIX, &block_height.to_string());
+
+        Ok(())
+    }
+
+    /// Rewind the state to right after `block_height` was indexed, replaying the undo logs of
+    /// every later block backwards in a single batch. Fails with `NotFound` when no block after
+    /// `block_height` is indexed.
+    pub fn rollback_to(&mut self, block_height: u64) -> Result<(), DatabaseError> {
+        let last_block: u64 = self.get(INDEXER_LAST_BLOCK_PREFIX, "")?;
+        // the last block marker must never move forward
+        if block_height >= last_block {
+            return Err(DatabaseError::NotFound);
+        }
+
+        // fail before touching the state if an undo log was already pruned
+        for height in (block_height + 1)..=last_block {
+            if self
+                .read(&(UNDO_LOG_PREFIX, height.to_be_bytes().to_vec()))
+                .is_none()
+            {
+                return Err(DatabaseError::NotFound);
+            }
+        }
+
+        self.begin_batch();
+        for height in ((block_height + 1)..=last_block).rev() {
+            if let Err(error) = self.rollback_block(height) {
+                self.discard_batch();
+                return Err(error);
+            }
+        }
+        self.put(INDEXER_LAST_BLOCK_PREFIX, "", block_height);
+        self.commit_batch()
+            .expect("Error writing batch into database");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_batch_read_your_writes() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        database.put(MESSAGE_PREFIX, "1:0", 10u64);
+        database.put(MESSAGE_PREFIX, "1:1", 11u64);
+
+        database.begin_batch();
+        database.put(MESSAGE_PREFIX, "1:2", 12u64);
+        database.delete(MESSAGE_PREFIX, "1:0");
+
+        // reads inside the batch see the pending writes
+        assert_eq!(database.get::<u64>(MESSAGE_PREFIX, "1:2").unwrap(), 12);
+        assert!(database.get::<u64>(MESSAGE_PREFIX, "1:0").is_err());
+        let values: Vec<(String, u64)> = database.expensive_find_by_prefix(MESSAGE_PREFIX).unwrap();
+        assert_eq!(
+            values,
+            vec![
+                ("message:1:1".to_string(), 11),
+                ("message:1:2".to_string(), 12)
+            ]
+        );
+
+        // nothing reaches rocksdb before commit
+        let stored = |database: &Database, key: &str| {
+            let (name, key) = column_key(MESSAGE_PREFIX, key).unwrap();
+            database
+                .db
+                .get_cf(database.cf(name), key)
+                .unwrap()
+                .is_some()
+        };
+        assert!(!stored(&database, "1:2"));
+        assert!(stored(&database, "1:0"));
+
+        database.commit_batch().unwrap();
+
+        assert!(stored(&database, "1:2"));
+        assert!(!stored(&database, "1:0"));
+    }
+
+    #[test]
+    fn test_binary_keys() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        // numeric order, not string order
+        database.put(MESSAGE_PREFIX, "10:0", 2u64);
+        database.put(MESSAGE_PREFIX, "9:1", 1u64);
+        let outpoint = "0c6f7c5a2ff9a7e2bb3cd2ed8df4e1d3b81e4d5d0e5a09b0c1fd0d0c2b5b8f3a:1";
+        database.put(ASSET_LIST_PREFIX, outpoint, 3u64);
+
+        assert_eq!(
+            database
+                .expensive_find_by_prefix::<u64>(MESSAGE_PREFIX)
+                .unwrap(),
+            vec![
+                ("message:9:1".to_string(), 1),
+                ("message:10:0".to_string(), 2)
+            ]
+        );
+        assert_eq!(
+            database
+                .expensive_find_keys_by_prefix(ASSET_LIST_PREFIX)
+                .unwrap(),
+            vec![format!("asset_list:{}", outpoint)]
+        );
+        assert_eq!(column_key(ASSET_LIST_PREFIX, outpoint).unwrap().1.len(), 36);
+
+        // malformed keys are never stored
+        assert!(database
+            .get::<u64>(ASSET_LIST_PREFIX, "not an outpoint")
+            .is_err());
+    }
+
+    #[test]
+    fn test_batch_discard() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        database.begin_batch();
+        database.put(INDEXER_LAST_BLOCK_PREFIX, "", 5u64);
+        database.discard_batch();
+
+        assert!(database.get::<u64>(INDEXER_LAST_BLOCK_PREFIX, "").is_err());
+    }
+
+    #[test]
+    fn test_savepoint_rollback() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        database.begin_batch();
+        database.begin_journal();
+        database.put(MESSAGE_PREFIX, "1:0", 10u64);
+
+        database.begin_savepoint();
+        database.put(MESSAGE_PREFIX, "1:0", 11u64);
+        database.put(MESSAGE_PREFIX, "1:0", 12u64);
+        database.put(MESSAGE_PREFIX, "1:1", 13u64);
+        database.rollback_savepoint();
+
+        assert_eq!(database.get::<u64>(MESSAGE_PREFIX, "1:0").unwrap(), 10);
+        assert!(database.get::<u64>(MESSAGE_PREFIX, "1:1").is_err());
+
+        database.begin_savepoint();
+        database.put(MESSAGE_PREFIX, "1:1", 14u64);
+        database.release_savepoint();
+        database.commit_batch().unwrap();
+
+        assert_eq!(database.get::<u64>(MESSAGE_PREFIX, "1:1").unwrap(), 14);
+
+        // the undo log still restores the values before the block
+        let undo_log = database.finish_journal();
+        assert_eq!(undo_log.entries.len(), 2);
+        assert!(undo_log
+            .entries
+            .iter()
+            .all(|entry| entry.previous.is_none()));
+    }
+
+    #[test]
+    fn test_prune_block_undo_below_cutoff() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        for height in 1..=5u64 {
+            database.put_block_undo(height, height.to_string(), BlockUndoLog::default());
+        }
+
+        // a lowered retention prunes the older undo logs too
+        database.begin_batch();
+        database.prune_block_undo(3);
+        database.commit_batch().unwrap();
+
+        assert_eq!(
+            database
+                .expensive_find_keys_by_prefix(UNDO_LOG_PREFIX)
+                .unwrap(),
+            vec!["undo_log:4".to_string(), "undo_log:5".to_string()]
+        );
+        assert_eq!(database.get_block_hash(1), Some("1".to_string()));
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+
+        database.begin_batch();
+        database.put(MESSAGE_PREFIX, "1:0", 10u64);
+        database.put(INDEXER_LAST_BLOCK_PREFIX, "", 1u64);
+        database.commit_batch().unwrap();
+
+        let snapshot = Database::snapshot(&database.db);
+
+        // the next block is not visible to the snapshot
+        database.begin_batch();
+        database.put(MESSAGE_PREFIX, "2:0", 20u64);
+        database.delete(MESSAGE_PREFIX, "1:0");
+        database.put(INDEXER_LAST_BLOCK_PREFIX, "", 2u64);
+        database.commit_batch().unwrap();
+
+        assert_eq!(snapshot.block_height(), Some(1));
+        assert_eq!(snapshot.get::<u64>(MESSAGE_PREFIX, "1:0").unwrap(), 10);
+        assert_eq!(
+            snapshot
+                .expensive_find_keys_by_prefix(MESSAGE_PREFIX)
+                .unwrap(),
+            vec!["message:1:0".to_string()]
+        );
+
+        assert_eq!(database.block_height(), Some(2));
+        assert_eq!(
+            Database::snapshot(&database.db)
+                .expensive_find_keys_by_prefix(MESSAGE_PREFIX)
+                .unwrap(),
+            vec!["message:2:0".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_overlay_writes_stay_in_memory() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+        database.put(MESSAGE_PREFIX, "1:0", 10u64);
+
+        let mut overlay = Database::overlay(&database.db);
+        overlay.put(MESSAGE_PREFIX, "1:0", 11u64);
+        overlay.put(MESSAGE_PREFIX, "2:0", 20u64);
+        assert_eq!(overlay.get::<u64>(MESSAGE_PREFIX, "1:0").unwrap(), 11);
+        assert_eq!(overlay.get::<u64>(MESSAGE_PREFIX, "2:0").unwrap(), 20);
+
+        drop(overlay);
+        assert_eq!(database.get::<u64>(MESSAGE_PREFIX, "1:0").unwrap(), 10);
+        assert!(database.get::<u64>(MESSAGE_PREFIX, "2:0").is_err());
+    }
+
+    #[test]
+    fn test_snapshot_outlives_database() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let mut database = Database::new(tempdir.path().to_str().unwrap().to_string());
+        database.put(MESSAGE_PREFIX, "1:0", 10u64);
+
+        let snapshot = Database::snapshot(&database.db);
+        // the snapshot keeps its own handle to the database
+        drop(database);
+
+        assert_eq!(snapshot.get::<u64>(MESSAGE_PREFIX, "1:0").unwrap(), 10);
     }
 }
@@ -0,0 +1,594 @@
+use super::*;
+use database::{
+    column_key, ColumnKey, Database, ADDRESS_ASSET_LIST_PREFIX, ASSET_CONTRACT_DATA_PREFIX,
+    ASSET_LIST_PREFIX, COLLATERALIZED_CONTRACT_DATA, COLLATERAL_ACCOUNTS_PREFIX,
+    CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, INDEXER_LAST_BLOCK_PREFIX, MESSAGE_PREFIX,
+    OUTPOINT_TO_ADDRESS, SPEC_CONTRACT_OWNED_PREFIX, STATE_KEYS_PREFIX, TICKER_TO_BLOCK_TX_PREFIX,
+    TRANSACTION_TO_BLOCK_TX_PREFIX, UNDO_LOG_PREFIX, VESTING_CONTRACT_DATA_PREFIX,
+};
+use rocksdb::{IteratorMode, WriteBatch};
+use std::{cmp::Ordering, collections::HashMap};
+use transaction::message::OpReturnMessage;
+
+/// Layout of the records written by this version. Bump it and register a migration step
+/// whenever a stored record changes shape, old records would fail to deserialize otherwise.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Stored in the default column family, databases without it are at version 1.
+const SCHEMA_VERSION_KEY: &str = "schema_version";
+
+const MIGRATION_BATCH_SIZE: usize = 10_000;
+
+type Record = (ColumnKey, Vec<u8>);
+
+/// Upgrades the database from `from_version` to `to_version`.
+struct Migration {
+    from_version: u32,
+    to_version: u32,
+    description: &'static str,
+    migrate: fn(&Database) -> Result<(), Box<dyn Error>>,
+}
+
+/// Every migration step in version order.
+const MIGRATIONS: [Migration; 1] = [Migration {
+    from_version: 1,
+    to_version: 2,
+    description: "JSON records to column families with borsh values, collateral accounts get \
+                  shares of the collateral held by their contract",
+    migrate: migrate_json_layout,
+}];
+
+impl Database {
+    pub fn schema_version(&self) -> Result<u32, Box<dyn Error>> {
+        match self.db.get(SCHEMA_VERSION_KEY)? {
+            Some(version) => Ok(u32::try_from_slice(&version)?),
+            None => Ok(1),
+        }
+    }
+
+    /// Refuse databases written by another schema version.
+    pub fn check_schema_version(&self) -> Result<(), Box<dyn Error>> {
+        let version = self.schema_version()?;
+
+        match version.cmp(&SCHEMA_VERSION) {
+            Ordering::Equal => Ok(()),
+            Ordering::Less => Err(format!(
+                "Database schema version {} is outdated, stop the node and run `glittr migrate` to upgrade it to version {}",
+                version, SCHEMA_VERSION
+            )
+            .into()),
+            Ordering::Greater => Err(format!(
+                "Database schema version {} is newer than the supported version {}",
+                version, SCHEMA_VERSION
+            )
+            .into()),
+        }
+    }
+
+    /// Apply the migration steps from the stored schema version in place. The version is stored
+    /// after every step, an interrupted migration restarts from the last unfinished step.
+    pub fn migrate(&self) -> Result<(), Box<dyn Error>> {
+        let version = self.schema_version()?;
+        if version > SCHEMA_VERSION {
+            return self.check_schema_version();
+        }
+
+        if version < SCHEMA_VERSION {
+            self.clear_undo_logs()?;
+        }
+
+        let mut version = version;
+        while version < SCHEMA_VERSION {
+            let migration = MIGRATIONS
+                .iter()
+                .find(|migration| migration.from_version == version)
+                .ok_or_else(|| format!("No migration from schema version {}", version))?;
+
+            log::info!(
+                "Migrating the database from version {} to {}: {}",
+                migration.from_version,
+                migration.to_version,
+                migration.description
+            );
+
+            (migration.migrate)(self)?;
+            self.set_schema_version(migration.to_version)?;
+            version = migration.to_version;
+        }
+
+        Ok(())
+    }
+
+    /// The undo logs hold the records in the layout they were written with, a rollback across
+    /// a migration would restore records that no longer deserialize. They are dropped before the
+    /// first step, so an interrupted migration never leaves old undo logs behind.
+    fn clear_undo_logs(&self) -> Result<(), Box<dyn Error>> {
+        let undo_logs = self
+            .db
+            .cf_handle(UNDO_LOG_PREFIX)
+            .ok_or("Column family not found")?;
+
+        // undo logs are keyed by their big endian block height
+        let mut write_batch = WriteBatch::default();
+        write_batch.delete_range_cf(undo_logs, &[0u8; 8][..], &[0xffu8; 9][..]);
+        self.db.write(write_batch)?;
+        self.db.flush()?;
+        log::info!(
+            "Dropped the undo logs, blocks indexed before the migration can't be rolled back"
+        );
+
+        Ok(())
+    }
+
+    /// Store the version of databases that don't have one yet. Version 1 databases keep their
+    /// records in the default column family and are left for `migrate`.
+    pub(super) fn init_schema_version(&self) -> Result<(), Box<dyn Error>> {
+        if self.db.get(SCHEMA_VERSION_KEY)?.is_some()
+            || self.db.iterator(IteratorMode::Start).next().is_some()
+        {
+            return Ok(());
+        }
+
+        self.set_schema_version(SCHEMA_VERSION)
+    }
+
+    fn set_schema_version(&self, version: u32) -> Result<(), Box<dyn Error>> {
+        self.db.put(SCHEMA_VERSION_KEY, borsh::to_vec(&version)?)?;
+        self.db.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Move the records of the JSON layout, `prefix:key` keys with JSON values in the default
+/// column family, into their column family with binary keys and borsh values.
+/// Each batch writes the converted records and deletes the old ones, an interrupted migration
+/// resumes with the records left. The collateral accounts are moved as they are and converted
+/// once every record is moved, see `convert_collateral_accounts`.
+fn migrate_json_layout(database: &Database) -> Result<(), Box<dyn Error>> {
+    let mut migrated = 0;
+    loop {
+        let mut write_batch = WriteBatch::default();
+        let mut count = 0;
+
+        for item in database.db.iterator(IteratorMode::Start) {
+            let (key, value) = item?;
+            write_batch.delete(&key);
+
+            let key = String::from_utf8(key.to_vec())?;
+            let (prefix, record_key) = key.split_once(':').ok_or("Invalid legacy key")?;
+            match convert_record(prefix, record_key, &value)? {
+                Some(((name, binary_key), value)) => write_batch.put_cf(
+                    database
+                        .db
+                        .cf_handle(name)
+                        .ok_or("Column family not found")?,
+                    binary_key,
+                    value,
+                ),
+                None => log::warn!("Dropping legacy record {}", key),
+            }
+
+            count += 1;
+            if count == MIGRATION_BATCH_SIZE {
+                break;
+            }
+        }
+
+        if count == 0 {
+            break;
+        }
+
+        database.db.write(write_batch)?;
+        migrated += count;
+        log::info!("Migrated {} records", migrated);
+    }
+
+    database.db.flush()?;
+
+    convert_collateral_accounts(database)
+}
+
+/// Column family key and borsh value of a legacy record, None for records that can't be kept.
+fn convert_record(prefix: &str, key: &str, json: &[u8]) -> Result<Option<Record>, Box<dyn Error>> {
+    let Some(value) = convert_value(prefix, json)? else {
+        return Ok(None);
+    };
+    let column_key =
+        column_key(prefix, key).ok_or_else(|| format!("Invalid legacy key {}:{}", prefix, key))?;
+
+    Ok(Some((column_key, value)))
+}
+
+fn convert_value(prefix: &str, json: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    fn reencode<T: for<'a> Deserialize<'a> + BorshSerialize>(
+        json: &[u8],
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        let value: T = serde_json::from_slice(json)?;
+        Ok(Some(borsh::to_vec(&value)?))
+    }
+
+    match prefix {
+        INDEXER_LAST_BLOCK_PREFIX => reencode::<u64>(json),
+        OUTPOINT_TO_ADDRESS => reencode::<String>(json),
+        MESSAGE_PREFIX => {
+            let outcome: MessageDataOutcomeV1 = serde_json::from_slice(json)?;
+            Ok(Some(borsh::to_vec(&MessageDataOutcome {
+                message: outcome.message,
+                flaw: outcome.flaw,
+                call_flaws: None,
+            })?))
+        }
+        TRANSACTION_TO_BLOCK_TX_PREFIX | TICKER_TO_BLOCK_TX_PREFIX => {
+            reencode::<BlockTxTuple>(json)
+        }
+        ASSET_LIST_PREFIX => reencode::<AssetList>(json),
+        ASSET_CONTRACT_DATA_PREFIX => reencode::<AssetContractData>(json),
+        VESTING_CONTRACT_DATA_PREFIX => reencode::<VestingContractData>(json),
+        // converted to shares once every record is moved
+        COLLATERAL_ACCOUNTS_PREFIX => reencode::<CollateralAccountsV1>(json),
+        COLLATERALIZED_CONTRACT_DATA => {
+            let pool: CollateralizedAssetDataV1 = serde_json::from_slice(json)?;
+            Ok(Some(borsh::to_vec(&CollateralizedAssetData {
+                amounts: pool.amounts,
+                total_supply: pool.total_supply,
+                shares: HashMap::new(),
+            })?))
+        }
+        STATE_KEYS_PREFIX => reencode::<StateKeys>(json),
+        SPEC_CONTRACT_OWNED_PREFIX => reencode::<SpecContractOwned>(json),
+        #[cfg(feature = "helper-api")]
+        ADDRESS_ASSET_LIST_PREFIX => reencode::<AddressAssetList>(json),
+        // helper indexes are only readable with the helper-api feature
+        #[cfg(not(feature = "helper-api"))]
+        ADDRESS_ASSET_LIST_PREFIX => Ok(None),
+        _ => Err(format!("Unknown legacy record prefix {}", prefix).into()),
+    }
+}
+
+/// Version 1 `MessageDataOutcome`, the messages are decoded by field name with the current
+/// types, the fields added since then are optional.
+#[derive(Deserialize)]
+struct MessageDataOutcomeV1 {
+    message: Option<OpReturnMessage>,
+    flaw: Option<Flaw>,
+}
+
+/// Version 1 `CollateralizedAssetData`, the reserves of a proportional pool.
+#[derive(Deserialize)]
+struct CollateralizedAssetDataV1 {
+    amounts: HashMap<BlockTxString, u128>,
+    total_supply: u128,
+}
+
+/// Version 1 collateral account, the account held its collateral and the share amount was
+/// given by the owner.
+#[derive(Deserialize, BorshSerialize, BorshDeserialize)]
+struct CollateralAccountV1 {
+    collateral_amounts: Vec<(BlockTxTuple, u128)>,
+    total_collateral_amount: u128,
+    ltv: Fraction,
+    amount_outstanding: u128,
+    share_amount: u128,
+}
+
+#[derive(Deserialize, BorshSerialize, BorshDeserialize)]
+struct CollateralAccountsV1 {
+    collateral_accounts: HashMap<String, CollateralAccountV1>,
+}
+
+/// Version 1 accounts hold their collateral. It moves to the collateral of the contract and
+/// each account gets one share per unit of each of its collateral assets, the value of a share
+/// at the start. The accounts are indexed by contract.
+/// The accounts, the collateral of their contracts, the index and the schema version are
+/// written in a single batch, the moved accounts are never converted twice.
+fn convert_collateral_accounts(database: &Database) -> Result<(), Box<dyn Error>> {
+    let mut write_batch = WriteBatch::default();
+    let mut pools: HashMap<String, CollateralizedAssetData> = HashMap::new();
+    let mut contract_collateral_accounts: HashMap<String, ContractCollateralAccounts> =
+        HashMap::new();
+
+    let accounts = database
+        .db
+        .cf_handle(COLLATERAL_ACCOUNTS_PREFIX)
+        .ok_or("Column family not found")?;
+    let mut migrated = 0;
+    for (key, legacy) in database
+        .expensive_find_by_prefix::<CollateralAccountsV1>(COLLATERAL_ACCOUNTS_PREFIX)
+        .map_err(|_| "Failed to load the collateral accounts")?
+    {
+        let outpoint = key.trim_start_matches(&format!("{}:", COLLATERAL_ACCOUNTS_PREFIX));
+
+        let mut collateral_accounts = CollateralAccounts::default();
+        for (contract_id, account) in legacy.collateral_accounts {
+            let mut share_amounts = ShareAmounts::new();
+            let pool = pools.entry(contract_id.clone()).or_default();
+            for (asset_id, amount) in &account.collateral_amounts {
+                let asset_id = BlockTx::from_tuple(*asset_id).to_string();
+                for total in [
+                    share_amounts.entry(asset_id.clone()).or_default(),
+                    pool.amounts.entry(asset_id.clone()).or_default(),
+                    pool.shares.entry(asset_id).or_default(),
+                ] {
+                    *total = total.saturating_add(*amount);
+                }
+            }
+
+            contract_collateral_accounts
+                .entry(contract_id.clone())
+                .or_default()
+                .outpoints
+                .insert(outpoint.to_string());
+            collateral_accounts.collateral_accounts.insert(
+                contract_id,
+                CollateralAccount {
+                    ltv: account.ltv,
+                    amount_outstanding: account.amount_outstanding,
+                    share_amounts,
+                },
+            );
+        }
+
+        let (_, key) = column_key(COLLATERAL_ACCOUNTS_PREFIX, outpoint)
+            .ok_or_else(|| format!("Invalid outpoint {}", outpoint))?;
+        write_batch.put_cf(accounts, key, borsh::to_vec(&collateral_accounts)?);
+        migrated += 1;
+    }
+
+    // version 1 account contracts don't hold collateral, shares can't be priced against it
+    let pools_handle = database
+        .db
+        .cf_handle(COLLATERALIZED_CONTRACT_DATA)
+        .ok_or("Column family not found")?;
+    for (contract_id, pool) in &pools {
+        let (_, key) = column_key(COLLATERALIZED_CONTRACT_DATA, contract_id)
+            .ok_or_else(|| format!("Invalid contract id {}", contract_id))?;
+        if database.db.get_cf(pools_handle, &key)?.is_some() {
+            return Err(format!(
+                "Account contract {} already holds collateral, the index must be resynced",
+                contract_id
+            )
+            .into());
+        }
+        write_batch.put_cf(pools_handle, key, borsh::to_vec(pool)?);
+    }
+
+    let index_handle = database
+        .db
+        .cf_handle(CONTRACT_COLLATERAL_ACCOUNTS_PREFIX)
+        .ok_or("Column family not found")?;
+    for (contract_id, outpoints) in &contract_collateral_accounts {
+        let (_, key) = column_key(CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, contract_id)
+            .ok_or_else(|| format!("Invalid contract id {}", contract_id))?;
+        write_batch.put_cf(index_handle, key, borsh::to_vec(outpoints)?);
+    }
+
+    write_batch.put(SCHEMA_VERSION_KEY, borsh::to_vec(&SCHEMA_VERSION)?);
+    database.db.write(write_batch)?;
+    database.db.flush()?;
+    log::info!(
+        "Migrated {} collateral accounts of {} contracts",
+        migrated,
+        pools.len()
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashSet;
+    use transaction::message::ContractType;
+
+    const OUTPOINT: &str = "0c6f7c5a2ff9a7e2bb3cd2ed8df4e1d3b81e4d5d0e5a09b0c1fd0d0c2b5b8f3a:1";
+
+    /// Database written before versioning, every record in the default column family.
+    fn write_version_1_fixture(path: &str, records: &[(String, &str)]) {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        let db = rocksdb::DB::open(&options, path).unwrap();
+
+        for (key, value) in records {
+            db.put(key, value).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_migrate_from_version_1() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().to_str().unwrap().to_string();
+        write_version_1_fixture(
+            &path,
+            &[
+                ("last_block:".to_string(), "7"),
+                ("ticker_to_blocktx:FOO".to_string(), "[1,0]"),
+                (format!("asset_list:{}", OUTPOINT), r#"{"list":{"1:0":10}}"#),
+                (
+                    "message:1:0".to_string(),
+                    r#"{"message":{"contract_creation":{"contract_type":{"moa":{"divisibility":18,"live_time":0,"mint_mechanism":{"free_mint":{"amount_per_mint":"10"}}}}}},"flaw":null}"#,
+                ),
+                (
+                    "message:2:0".to_string(),
+                    r#"{"message":null,"flaw":"supply_cap_exceeded"}"#,
+                ),
+            ],
+        );
+
+        let mut database = Database::new(path.clone());
+        assert_eq!(database.schema_version().unwrap(), 1);
+        assert!(database.check_schema_version().is_err());
+
+        database.migrate().unwrap();
+        assert_eq!(database.schema_version().unwrap(), SCHEMA_VERSION);
+        assert!(database.check_schema_version().is_ok());
+        assert_eq!(
+            database
+                .db
+                .iterator(IteratorMode::Start)
+                .filter(|item| item.as_ref().unwrap().0.as_ref() != SCHEMA_VERSION_KEY.as_bytes())
+                .count(),
+            0
+        );
+
+        assert_eq!(
+            database.get::<u64>(INDEXER_LAST_BLOCK_PREFIX, "").unwrap(),
+            7
+        );
+        assert_eq!(
+            database
+                .get::<BlockTxTuple>(TICKER_TO_BLOCK_TX_PREFIX, "FOO")
+                .unwrap(),
+            (1, 0)
+        );
+        let migrated: AssetList = database.get(ASSET_LIST_PREFIX, OUTPOINT).unwrap();
+        assert_eq!(migrated.list.get("1:0"), Some(&10));
+
+        // the fields added since version 1 are None
+        let outcome: MessageDataOutcome = database.get(MESSAGE_PREFIX, "1:0").unwrap();
+        let ContractType::Moa(moa) = outcome
+            .message
+            .unwrap()
+            .contract_creation
+            .unwrap()
+            .contract_type
+        else {
+            panic!("moa contract expected");
+        };
+        assert_eq!(moa.mint_mechanism.free_mint.unwrap().amount_per_mint.0, 10);
+        assert!(moa.commitment.is_none());
+        assert!(outcome.flaw.is_none() && outcome.call_flaws.is_none());
+        let outcome: MessageDataOutcome = database.get(MESSAGE_PREFIX, "2:0").unwrap();
+        assert_eq!(outcome.flaw, Some(Flaw::SupplyCapExceeded));
+
+        // the blocks indexed before the migration have no undo log
+        assert!(database.rollback_to(6).is_err());
+
+        // migrating again is a no-op
+        database.migrate().unwrap();
+        drop(database);
+        assert!(Database::new(path).check_schema_version().is_ok());
+    }
+
+    fn legacy_collateral_accounts(contract_id: &str, collateral_amounts: &str) -> String {
+        format!(
+            r#"{{"collateral_accounts":{{"{}":{{"collateral_amounts":{},"total_collateral_amount":0,"ltv":[1,10],"amount_outstanding":500,"share_amount":100}}}}}}"#,
+            contract_id, collateral_amounts
+        )
+    }
+
+    #[test]
+    fn test_migrate_collateral_shares() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().to_str().unwrap().to_string();
+        let other_outpoint = OUTPOINT.replace(":1", ":2");
+        write_version_1_fixture(
+            &path,
+            &[
+                (
+                    format!("collateral_account:{}", OUTPOINT),
+                    &legacy_collateral_accounts("3:0", "[[[1,0],6000],[[2,0],4000]]"),
+                ),
+                (
+                    format!("collateral_account:{}", other_outpoint),
+                    &legacy_collateral_accounts("3:0", "[[[2,0],5000]]"),
+                ),
+                // a proportional pool
+                (
+                    "pool_data:4:0".to_string(),
+                    r#"{"amounts":{"1:0":6000,"2:0":9000},"total_supply":7348}"#,
+                ),
+            ],
+        );
+
+        let database = Database::new(path);
+        database.migrate().unwrap();
+        assert_eq!(database.schema_version().unwrap(), SCHEMA_VERSION);
+
+        let account = |outpoint: &str| {
+            let collateral_accounts: CollateralAccounts =
+                database.get(COLLATERAL_ACCOUNTS_PREFIX, outpoint).unwrap();
+            collateral_accounts.collateral_accounts["3:0"].clone()
+        };
+        assert_eq!(
+            account(OUTPOINT),
+            CollateralAccount {
+                ltv: (1, 10),
+                amount_outstanding: 500,
+                share_amounts: ShareAmounts::from([
+                    ("1:0".to_string(), 6_000),
+                    ("2:0".to_string(), 4_000)
+                ]),
+            }
+        );
+        assert_eq!(
+            account(&other_outpoint).share_amounts,
+            ShareAmounts::from([("2:0".to_string(), 5_000)])
+        );
+
+        let amounts = HashMap::from([("1:0".to_string(), 6_000), ("2:0".to_string(), 9_000)]);
+        let pool: CollateralizedAssetData =
+            database.get(COLLATERALIZED_CONTRACT_DATA, "3:0").unwrap();
+        assert_eq!(pool.amounts, amounts);
+        assert_eq!(pool.shares, amounts);
+
+        let pool: CollateralizedAssetData =
+            database.get(COLLATERALIZED_CONTRACT_DATA, "4:0").unwrap();
+        assert_eq!(pool.amounts, amounts);
+        assert_eq!(pool.total_supply, 7_348);
+        assert!(pool.shares.is_empty());
+
+        let contract_collateral_accounts: ContractCollateralAccounts = database
+            .get(CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, "3:0")
+            .unwrap();
+        assert_eq!(
+            contract_collateral_accounts.outpoints,
+            HashSet::from([OUTPOINT.to_string(), other_outpoint])
+        );
+    }
+
+    #[test]
+    fn test_migrate_collateral_shares_existing_collateral() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let path = tempdir.path().to_str().unwrap().to_string();
+        write_version_1_fixture(
+            &path,
+            &[
+                (
+                    format!("collateral_account:{}", OUTPOINT),
+                    &legacy_collateral_accounts("3:0", "[[[1,0],1000]]"),
+                ),
+                (
+                    "pool_data:3:0".to_string(),
+                    r#"{"amounts":{"1:0":5000},"total_supply":5000}"#,
+                ),
+            ],
+        );
+
+        // the existing collateral is left as is, the accounts are not converted
+        let database = Database::new(path);
+        assert!(database.migrate().is_err());
+        assert_eq!(database.schema_version().unwrap(), 1);
+        let pool: CollateralizedAssetData =
+            database.get(COLLATERALIZED_CONTRACT_DATA, "3:0").unwrap();
+        assert_eq!(pool.amounts, HashMap::from([("1:0".to_string(), 5_000)]));
+        assert!(database
+            .get::<CollateralAccountsV1>(COLLATERAL_ACCOUNTS_PREFIX, OUTPOINT)
+            .is_ok());
+
+        // a rerun doesn't convert the moved accounts either
+        assert!(database.migrate().is_err());
+        assert_eq!(database.schema_version().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_refuse_newer_schema_version() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let database = Database::new(tempdir.path().to_str().unwrap().to_string());
+        assert_eq!(database.schema_version().unwrap(), SCHEMA_VERSION);
+
+        database.set_schema_version(SCHEMA_VERSION + 1).unwrap();
+        assert!(database.check_schema_version().is_err());
+        assert!(database.migrate().is_err());
+    }
+}
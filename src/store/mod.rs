@@ -1,2 +1,5 @@
 use super::*;
 pub mod database;
+mod migration;
+
+pub use migration::SCHEMA_VERSION;
@@ -0,0 +1,645 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use super::*;
+use bitcoin::{
+    absolute::LockTime,
+    psbt::Psbt,
+    transaction::{predict_weight, InputWeightPrediction, Version},
+    Amount, FeeRate, OutPoint, PublicKey, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use flaw::Flaw;
+use message::{
+    AssertValues, CallType, CloseAccountOption, ContractCall, MintBurnOption, OpReturnMessage,
+    OpenAccountOption, OracleMessageSigned, SwapOption, Transfer, TxTypeTransfer,
+};
+use transaction_shared::{InputAsset, PurchaseBurnSwap};
+use updater::AssetList;
+
+// the indexer moves the assets left unallocated to the first output after the OP_RETURN
+const OP_RETURN_VOUT: usize = 0;
+const CHANGE_VOUT: u32 = 1;
+// relay policy of bitcoin core, 80 bytes of data after the OP_RETURN and push opcodes
+const MAX_STANDARD_OP_RETURN_LEN: usize = 83;
+
+/// Utxo spent by the transaction, with the glittr assets the indexer reports on it.
+#[derive(Clone, Debug)]
+pub struct FundingUtxo {
+    pub outpoint: OutPoint,
+    pub txout: TxOut,
+    pub asset_list: AssetList,
+}
+
+/// New output receiving an amount of a glittr asset.
+#[derive(Clone, Debug)]
+pub struct AssetRecipient {
+    pub asset: BlockTxTuple,
+    pub amount: u128,
+    pub script_pubkey: ScriptBuf,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug)]
+pub enum Intent {
+    Transfer(Vec<AssetRecipient>),
+    FreeMint {
+        contract: BlockTxTuple,
+        recipient: ScriptBuf,
+    },
+    /// Pays `amount` of the input asset of the purchase mechanism of the contract.
+    Purchase {
+        contract: BlockTxTuple,
+        purchase: PurchaseBurnSwap,
+        amount: u128,
+        recipient: ScriptBuf,
+        oracle_message: Option<OracleMessageSigned>,
+    },
+    Swap {
+        contract: BlockTxTuple,
+        input_asset: BlockTxTuple,
+        amount: u128,
+        min_out_value: Option<u128>,
+        recipient: ScriptBuf,
+    },
+    /// The collateral account is issued to `key_recipient`.
+    OpenAccount {
+        contract: BlockTxTuple,
+        collateral: Vec<(BlockTxTuple, u128)>,
+        share_amount: u128,
+        key_recipient: ScriptBuf,
+    },
+    /// One of the funding utxos must hold the collateral account of the contract.
+    CloseAccount {
+        contract: BlockTxTuple,
+        recipient: ScriptBuf,
+    },
+}
+
+#[derive(Debug)]
+pub enum BuilderError {
+    InsufficientAsset(BlockTxTuple),
+    InsufficientFunds,
+    UnsupportedScript(OutPoint),
+    UnsupportedInputAsset,
+    InvalidPubkey,
+    InvalidMessage(Flaw),
+    MessageTooLarge,
+}
+
+/// Builds the unsigned transaction of an intent. The outputs are the OP_RETURN, the change
+/// and the outputs of the intent in this order, so the change receives the glittr assets
+/// left in the inputs. Every funding utxo is spent, they must be p2wpkh or p2tr outputs.
+/// Messages that don't fit in a standard OP_RETURN are rejected, they need an envelope.
+pub struct TransactionBuilder {
+    funding_utxos: Vec<FundingUtxo>,
+    change_script: ScriptBuf,
+    fee_rate: FeeRate,
+}
+
+#[derive(Default)]
+struct Draft {
+    outputs: Vec<TxOut>,
+    transfers: Vec<TxTypeTransfer>,
+    // asset amounts moved to the outputs by the transfers
+    sent: BTreeMap<BlockTxTuple, u128>,
+    // asset amounts taken by the call, it takes every unit of the assets left in the inputs
+    consumed: Option<BTreeMap<BlockTxTuple, u128>>,
+    op_return_value: u64,
+}
+
+impl Draft {
+    fn add_output(&mut self, script_pubkey: ScriptBuf, value: Amount) -> u32 {
+        self.outputs.push(TxOut {
+            value,
+            script_pubkey,
+        });
+        self.outputs.len() as u32 - 1
+    }
+
+    fn add_recipient(&mut self, script_pubkey: ScriptBuf) -> u32 {
+        let value = script_pubkey.minimal_non_dust();
+        self.add_output(script_pubkey, value)
+    }
+
+    fn transfer(&mut self, asset: BlockTxTuple, output: u32, amount: u128) {
+        self.transfers.push(TxTypeTransfer {
+            asset,
+            output,
+            amount: U128(amount),
+        });
+        let sent = self.sent.entry(asset).or_default();
+        *sent = sent.saturating_add(amount);
+    }
+
+    fn consume(&mut self, asset: BlockTxTuple, amount: u128) {
+        let consumed = self
+            .consumed
+            .get_or_insert_with(BTreeMap::new)
+            .entry(asset)
+            .or_default();
+        *consumed = consumed.saturating_add(amount);
+    }
+}
+
+fn pay_to_key_script(pubkey: &Pubkey) -> Result<ScriptBuf, BuilderError> {
+    let wpubkey_hash = PublicKey::from_slice(pubkey)
+        .ok()
+        .and_then(|pubkey| pubkey.wpubkey_hash().ok())
+        .ok_or(BuilderError::InvalidPubkey)?;
+
+    Ok(ScriptBuf::new_p2wpkh(&wpubkey_hash))
+}
+
+impl TransactionBuilder {
+    pub fn new(
+        funding_utxos: Vec<FundingUtxo>,
+        change_script: ScriptBuf,
+        fee_rate: FeeRate,
+    ) -> Self {
+        TransactionBuilder {
+            funding_utxos,
+            change_script,
+            fee_rate,
+        }
+    }
+
+    fn held_assets(&self) -> BTreeMap<BlockTxTuple, u128> {
+        let mut held = BTreeMap::new();
+        for funding_utxo in &self.funding_utxos {
+            for (asset, amount) in &funding_utxo.asset_list.list {
+                if let Ok(asset) = BlockTx::from_str(asset) {
+                    let held = held.entry(asset.to_tuple()).or_default();
+                    *held = u128::saturating_add(*held, *amount);
+                }
+            }
+        }
+
+        held
+    }
+
+    fn contract_call(
+        &self,
+        draft: &mut Draft,
+        intent: &Intent,
+    ) -> Result<Option<ContractCall>, BuilderError> {
+        let (contract, call_type) = match intent {
+            Intent::Transfer(recipients) => {
+                for recipient in recipients {
+                    let output = draft.add_recipient(recipient.script_pubkey.clone());
+                    draft.transfer(recipient.asset, output, recipient.amount);
+                }
+                return Ok(None);
+            }
+            Intent::FreeMint {
+                contract,
+                recipient,
+            } => {
+                let pointer = draft.add_recipient(recipient.clone());
+                (contract, CallType::Mint(mint_option(pointer, None)))
+            }
+            Intent::Purchase {
+                contract,
+                purchase,
+                amount,
+                recipient,
+                oracle_message,
+            } => {
+                let pointer = draft.add_recipient(recipient.clone());
+                let pay_to_script = match &purchase.pay_to_key {
+                    Some(pay_to_key) => Some(pay_to_key_script(pay_to_key)?),
+                    None => None,
+                };
+
+                match (purchase.input_asset, pay_to_script) {
+                    (InputAsset::RawBtc, Some(pay_to_script)) => {
+                        let value = Amount::from_sat(
+                            u64::try_from(*amount).map_err(|_| BuilderError::InsufficientFunds)?,
+                        );
+                        draft.add_output(pay_to_script, value);
+                    }
+                    // sats burned in the OP_RETURN
+                    (InputAsset::RawBtc, None) => {
+                        draft.op_return_value =
+                            u64::try_from(*amount).map_err(|_| BuilderError::InsufficientFunds)?;
+                    }
+                    (InputAsset::GlittrAsset(asset), Some(pay_to_script)) => {
+                        let output = draft.add_recipient(pay_to_script);
+                        draft.transfer(asset, output, *amount);
+                    }
+                    (InputAsset::GlittrAsset(asset), None) => draft.consume(asset, *amount),
+                    _ => return Err(BuilderError::UnsupportedInputAsset),
+                }
+
+                let mint_option = mint_option(pointer, oracle_message.clone());
+                (contract, CallType::Mint(mint_option))
+            }
+            Intent::Swap {
+                contract,
+                input_asset,
+                amount,
+                min_out_value,
+                recipient,
+            } => {
+                draft.consume(*input_asset, *amount);
+                let pointer = draft.add_recipient(recipient.clone());
+                let assert_values = min_out_value.map(|min_out_value| AssertValues {
+                    input_values: None,
+                    total_collateralized: None,
+                    min_out_value: Some(U128(min_out_value)),
+                });
+                (
+                    contract,
+                    CallType::Swap(SwapOption {
+                        pointer,
+                        assert_values,
+                    }),
+                )
+            }
+            Intent::OpenAccount {
+                contract,
+                collateral,
+                share_amount,
+                key_recipient,
+            } => {
+                for (asset, amount) in collateral {
+                    draft.consume(*asset, *amount);
+                }
+                let pointer_to_key = draft.add_recipient(key_recipient.clone());
+                (
+                    contract,
+                    CallType::OpenAccount(OpenAccountOption {
+                        pointer_to_key,
+                        share_amount: U128(*share_amount),
+                    }),
+                )
+            }
+            Intent::CloseAccount {
+                contract,
+                recipient,
+            } => {
+                let pointer = draft.add_recipient(recipient.clone());
+                (
+                    contract,
+                    CallType::CloseAccount(CloseAccountOption { pointer }),
+                )
+            }
+        };
+
+        Ok(Some(ContractCall {
+            contract: Some(*contract),
+            call_type,
+        }))
+    }
+
+    pub fn build(&self, intent: &Intent) -> Result<Psbt, BuilderError> {
+        let mut draft = Draft::default();
+        // the OP_RETURN is set once the message is complete
+        draft.add_output(ScriptBuf::new(), Amount::ZERO);
+        draft.add_output(self.change_script.clone(), Amount::ZERO);
+
+        let contract_call = self.contract_call(&mut draft, intent)?;
+
+        let held = self.held_assets();
+        let mut required = draft.sent.clone();
+        for (asset, amount) in draft.consumed.iter().flatten() {
+            let required = required.entry(*asset).or_default();
+            *required = required.saturating_add(*amount);
+        }
+        for (asset, amount) in &required {
+            if held.get(asset).copied().unwrap_or_default() < *amount {
+                return Err(BuilderError::InsufficientAsset(*asset));
+            }
+        }
+
+        // the call takes the whole balance of the inputs, the rest goes back to the change
+        if draft.consumed.is_some() {
+            for (asset, amount) in &held {
+                let left = amount - required.get(asset).copied().unwrap_or_default();
+                if left > 0 {
+                    draft.transfer(*asset, CHANGE_VOUT, left);
+                }
+            }
+        }
+
+        let message = OpReturnMessage {
+            transfer: (!draft.transfers.is_empty()).then(|| Transfer {
+                transfers: draft.transfers.clone(),
+                nft_transfers: None,
+            }),
+            contract_creation: None,
+            contract_call,
+        };
+        if let Some(flaw) = message.validate() {
+            return Err(BuilderError::InvalidMessage(flaw));
+        }
+        let script_pubkey = message.into_script();
+        if script_pubkey.len() > MAX_STANDARD_OP_RETURN_LEN {
+            return Err(BuilderError::MessageTooLarge);
+        }
+        draft.outputs[OP_RETURN_VOUT] = TxOut {
+            value: Amount::from_sat(draft.op_return_value),
+            script_pubkey,
+        };
+
+        let mut input_weights = Vec::new();
+        for funding_utxo in &self.funding_utxos {
+            let script_pubkey = &funding_utxo.txout.script_pubkey;
+            if script_pubkey.is_p2wpkh() {
+                input_weights.push(InputWeightPrediction::P2WPKH_MAX);
+            } else if script_pubkey.is_p2tr() {
+                input_weights.push(InputWeightPrediction::P2TR_KEY_DEFAULT_SIGHASH);
+            } else {
+                return Err(BuilderError::UnsupportedScript(funding_utxo.outpoint));
+            }
+        }
+        let weight = predict_weight(
+            input_weights,
+            draft
+                .outputs
+                .iter()
+                .map(|output| output.script_pubkey.len()),
+        );
+        let fee = self
+            .fee_rate
+            .fee_wu(weight)
+            .ok_or(BuilderError::InsufficientFunds)?;
+
+        let input_value: Amount = self
+            .funding_utxos
+            .iter()
+            .map(|funding_utxo| funding_utxo.txout.value)
+            .sum();
+        let output_value: Amount = draft.outputs.iter().map(|output| output.value).sum();
+        let change = input_value
+            .checked_sub(output_value)
+            .and_then(|change| change.checked_sub(fee))
+            .filter(|change| *change >= self.change_script.minimal_non_dust())
+            .ok_or(BuilderError::InsufficientFunds)?;
+        draft.outputs[CHANGE_VOUT as usize].value = change;
+
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: self
+                .funding_utxos
+                .iter()
+                .map(|funding_utxo| TxIn {
+                    previous_output: funding_utxo.outpoint,
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: Witness::new(),
+                })
+                .collect(),
+            output: draft.outputs,
+        };
+
+        // the inputs are unsigned
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        for (input, funding_utxo) in psbt.inputs.iter_mut().zip(&self.funding_utxos) {
+            input.witness_utxo = Some(funding_utxo.txout.clone());
+        }
+
+        Ok(psbt)
+    }
+}
+
+fn mint_option(pointer: u32, oracle_message: Option<OracleMessageSigned>) -> MintBurnOption {
+    MintBurnOption {
+        pointer: Some(pointer),
+        oracle_message,
+        pointer_to_key: None,
+        assert_values: None,
+        commitment_message: None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::key::{Keypair, Secp256k1};
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{PubkeyHash, Txid, WPubkeyHash, XOnlyPublicKey};
+    use std::collections::HashMap;
+
+    use super::transaction_shared::RatioType;
+    use super::*;
+
+    fn p2wpkh(n: u8) -> ScriptBuf {
+        ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([n; 20]))
+    }
+
+    fn funding_utxo(n: u8, script_pubkey: ScriptBuf, assets: &[(&str, u128)]) -> FundingUtxo {
+        FundingUtxo {
+            outpoint: OutPoint {
+                txid: Txid::from_byte_array([n; 32]),
+                vout: 0,
+            },
+            txout: TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey,
+            },
+            asset_list: AssetList {
+                list: assets
+                    .iter()
+                    .map(|(asset, amount)| (asset.to_string(), *amount))
+                    .collect::<HashMap<_, _>>(),
+            },
+        }
+    }
+
+    fn transfers(psbt: &Psbt) -> Vec<(BlockTxTuple, u32, u128)> {
+        let message = OpReturnMessage::parse_tx(&psbt.unsigned_tx).unwrap();
+        message
+            .transfer
+            .map(|transfer| transfer.transfers)
+            .unwrap_or_default()
+            .iter()
+            .map(|transfer| (transfer.asset, transfer.output, transfer.amount.0))
+            .collect()
+    }
+
+    #[test]
+    pub fn build_transfer() {
+        let fee_rate = FeeRate::from_sat_per_vb(2).unwrap();
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2wpkh(1), &[("1:0", 100)])],
+            p2wpkh(2),
+            fee_rate,
+        );
+        let psbt = builder
+            .build(&Intent::Transfer(vec![AssetRecipient {
+                asset: (1, 0),
+                amount: 40,
+                script_pubkey: p2wpkh(3),
+            }]))
+            .unwrap();
+
+        let outputs = &psbt.unsigned_tx.output;
+        assert_eq!(outputs.len(), 3);
+        assert!(outputs[0].script_pubkey.is_op_return());
+        assert_eq!(outputs[1].script_pubkey, p2wpkh(2));
+        assert_eq!(outputs[2].script_pubkey, p2wpkh(3));
+        assert_eq!(outputs[2].value, p2wpkh(3).minimal_non_dust());
+
+        // the 60 left go to the change with the fallback
+        assert_eq!(transfers(&psbt), vec![((1, 0), 2, 40)]);
+
+        let fee = psbt.fee().unwrap();
+        assert!(fee >= fee_rate.fee_vb(psbt.unsigned_tx.vsize() as u64).unwrap());
+        assert_eq!(
+            outputs[1].value,
+            Amount::from_sat(10_000) - outputs[2].value - fee
+        );
+        assert_eq!(
+            psbt.inputs[0].witness_utxo.as_ref().unwrap().script_pubkey,
+            p2wpkh(1)
+        );
+    }
+
+    #[test]
+    pub fn build_swap_returns_leftover() {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let p2tr = ScriptBuf::new_p2tr(&secp, XOnlyPublicKey::from_keypair(&keypair).0, None);
+
+        let builder = TransactionBuilder::new(
+            vec![
+                funding_utxo(1, p2wpkh(1), &[("1:0", 100)]),
+                funding_utxo(2, p2tr, &[("1:0", 20), ("2:0", 5)]),
+            ],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        );
+        let psbt = builder
+            .build(&Intent::Swap {
+                contract: (3, 0),
+                input_asset: (1, 0),
+                amount: 60,
+                min_out_value: Some(10),
+                recipient: p2wpkh(3),
+            })
+            .unwrap();
+
+        // the swap takes every unit left in the inputs
+        assert_eq!(transfers(&psbt), vec![((1, 0), 1, 60), ((2, 0), 1, 5)]);
+
+        let message = OpReturnMessage::parse_tx(&psbt.unsigned_tx).unwrap();
+        let contract_call = message.contract_call.unwrap();
+        assert_eq!(contract_call.contract, Some((3, 0)));
+        let CallType::Swap(swap_option) = contract_call.call_type else {
+            panic!("expected a swap call");
+        };
+        assert_eq!(swap_option.pointer, 2);
+        assert_eq!(
+            swap_option.assert_values.unwrap().min_out_value.unwrap().0,
+            10
+        );
+    }
+
+    #[test]
+    pub fn build_purchase() {
+        let secp = Secp256k1::new();
+        let pay_to_key = SecretKey::from_slice(&[1; 32])
+            .unwrap()
+            .public_key(&secp)
+            .serialize()
+            .to_vec();
+        let purchase = |input_asset: InputAsset, pay_to_key: Option<Pubkey>| Intent::Purchase {
+            contract: (3, 0),
+            purchase: PurchaseBurnSwap {
+                input_asset,
+                pay_to_key,
+                ratio: RatioType::Fixed { ratio: (1, 1) },
+            },
+            amount: 1_000,
+            recipient: p2wpkh(3),
+            oracle_message: None,
+        };
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2wpkh(1), &[("1:0", 1_500)])],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        );
+
+        // burned sats are the value of the OP_RETURN
+        let psbt = builder.build(&purchase(InputAsset::RawBtc, None)).unwrap();
+        assert_eq!(psbt.unsigned_tx.output[0].value, Amount::from_sat(1_000));
+
+        let psbt = builder
+            .build(&purchase(InputAsset::RawBtc, Some(pay_to_key.clone())))
+            .unwrap();
+        let output = &psbt.unsigned_tx.output[3];
+        assert_eq!(output.value, Amount::from_sat(1_000));
+        assert_eq!(
+            output.script_pubkey,
+            pay_to_key_script(&pay_to_key).unwrap()
+        );
+
+        let psbt = builder
+            .build(&purchase(InputAsset::GlittrAsset((1, 0)), Some(pay_to_key)))
+            .unwrap();
+        assert_eq!(transfers(&psbt), vec![((1, 0), 3, 1_000)]);
+
+        let psbt = builder
+            .build(&purchase(InputAsset::GlittrAsset((1, 0)), None))
+            .unwrap();
+        assert_eq!(transfers(&psbt), vec![((1, 0), 1, 500)]);
+    }
+
+    #[test]
+    pub fn build_errors() {
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2wpkh(1), &[("1:0", 100)])],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        );
+        let open_account = |amount: u128| Intent::OpenAccount {
+            contract: (3, 0),
+            collateral: vec![((1, 0), amount)],
+            share_amount: 1,
+            key_recipient: p2wpkh(3),
+        };
+        assert!(matches!(
+            builder.build(&open_account(101)),
+            Err(BuilderError::InsufficientAsset((1, 0)))
+        ));
+
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2wpkh(1), &[("1:0", 100)])],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(100).unwrap(),
+        );
+        assert!(matches!(
+            builder.build(&open_account(100)),
+            Err(BuilderError::InsufficientFunds)
+        ));
+
+        let p2pkh = ScriptBuf::new_p2pkh(&PubkeyHash::from_byte_array([1; 20]));
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2pkh, &[])],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        );
+        assert!(matches!(
+            builder.build(&open_account(0)),
+            Err(BuilderError::UnsupportedScript(_))
+        ));
+
+        // the transfers don't fit in a standard OP_RETURN
+        let builder = TransactionBuilder::new(
+            vec![funding_utxo(1, p2wpkh(1), &[("1:0", 1_000_000)])],
+            p2wpkh(2),
+            FeeRate::from_sat_per_vb(1).unwrap(),
+        );
+        let recipients = (0..20)
+            .map(|n| AssetRecipient {
+                asset: (1, 0),
+                amount: 40_000 + n as u128,
+                script_pubkey: p2wpkh(n + 3),
+            })
+            .collect();
+        assert!(matches!(
+            builder.build(&Intent::Transfer(recipients)),
+            Err(BuilderError::MessageTooLarge)
+        ));
+    }
+}
@@ -2,21 +2,24 @@ use std::fmt;
 
 use super::*;
 use bitcoin::{
+    blockdata::constants::MAX_SCRIPT_ELEMENT_SIZE,
     opcodes,
     script::{self, Instruction, PushBytes},
-    OutPoint, ScriptBuf, Transaction,
+    OutPoint, PublicKey, ScriptBuf, Transaction, XOnlyPublicKey,
 };
 use bitcoincore_rpc::jsonrpc::serde_json::{self, Deserializer};
-use constants::OP_RETURN_MAGIC_PREFIX;
+use constants::{OP_RETURN_FORMAT_BORSH, OP_RETURN_FORMAT_BORSH_DEFLATE, OP_RETURN_MAGIC_PREFIX};
 use flaw::Flaw;
+use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec_with_limit};
 use mint_burn_asset::MintBurnAssetContract;
 use mint_only_asset::MintOnlyAssetContract;
-use nft::NftAssetContract;
+use nft::{NftAssetContract, NftTokenMetadata};
 use spec::SpecContract;
 
+// a deflated payload can't inflate past the size of a block
+const MAX_INFLATED_PAYLOAD_SIZE: usize = 4_000_000;
 
-
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ContractType {
     Moa(MintOnlyAssetContract),
@@ -26,7 +29,7 @@ pub enum ContractType {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct MintBurnOption {
     pub pointer: Option<u32>,
     pub oracle_message: Option<OracleMessageSigned>,
@@ -36,14 +39,14 @@ pub struct MintBurnOption {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct SwapOption { 
     pub pointer: u32,
     pub assert_values: Option<AssertValues>
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct AssertValues {
     pub input_values: Option<Vec<U128>>,
     pub total_collateralized: Option<Vec<U128>>,
@@ -51,7 +54,7 @@ pub struct AssertValues {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum CallType {
     Mint(MintBurnOption),
@@ -59,38 +62,94 @@ pub enum CallType {
     Swap(SwapOption),
     // Collateralized assets
     OpenAccount(OpenAccountOption),
-    CloseAccount(CloseAccountOption), // TODO: partial return & fee
-    UpdateNft(UpdateNftOption)
+    CloseAccount(CloseAccountOption), // TODO: fee
+    UpdateNft(UpdateNftOption),
+    MintNft(MintNftOption),
+    AddCollateral(AddCollateralOption),
+    Repay(RepayOption),
+    WithdrawCollateral(WithdrawCollateralOption),
+    Liquidate(LiquidateOption),
+    // Calls run in order against the same inputs, a flawed call reverts the whole message
+    Batch(Vec<ContractCall>),
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct OpenAccountOption {
     pub pointer_to_key: u32,
-    pub share_amount: U128, // representation of total value of the inputs
+    pub share_amount: U128, // minimum shares issued for each asset of the inputs
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct CloseAccountOption {
     pub pointer: u32,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Adds the collateral assets of the inputs to the collateral account.
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct AddCollateralOption {
+    pub pointer_to_key: u32,
+    pub oracle_message: Option<OracleMessageSigned>,
+}
+
+/// Burns the asset of the inputs to reduce the outstanding amount of the collateral account,
+/// the asset left after the repayment goes to `pointer`.
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct RepayOption {
+    pub pointer: u32,
+    pub pointer_to_key: u32,
+    pub oracle_message: Option<OracleMessageSigned>,
+}
+
+/// Takes collateral out of the collateral account to `pointer`, the account must stay under
+/// the max ltv.
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct WithdrawCollateralOption {
+    pub pointer: u32,
+    pub pointer_to_key: u32,
+    pub amounts: Vec<(BlockTxTuple, U128)>,
+    pub oracle_message: Option<OracleMessageSigned>,
+}
+
+/// Repays the collateral account at the `input_outpoint` of the oracle message with the asset
+/// of the inputs, the seized collateral and the asset left go to `pointer`.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct LiquidateOption {
+    pub pointer: u32,
+    pub oracle_message: OracleMessageSigned,
+}
+
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct OracleMessageSigned {
     pub signature: Vec<u8>,
     pub message: OracleMessage,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct UpdateNftOption {
     pub whitelist_address_bloom_filter: Option<Vec<u8>>,
     pub trusted_marketplace_fee_addresses: Option<Vec<String>>, 
     pub access_key_pointer: Option<u64>
 }
 
+/// Mints the next token of an nft collection with its own metadata.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+pub struct MintNftOption {
+    pub pointer: u32,
+    #[serde(flatten)]
+    pub metadata: NftTokenMetadata,
+}
+
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct OracleMessage {
     /// the input_outpoint dictates which UTXO is being evaluated by the Oracle
+    #[borsh(
+        serialize_with = "borsh_option_outpoint::serialize",
+        deserialize_with = "borsh_option_outpoint::deserialize"
+    )]
     pub input_outpoint: Option<OutPoint>,
     /// min_in_value represents what the input valued at (minimum because btc value could differ (-fee))
     pub min_in_value: Option<U128>,
@@ -106,29 +165,41 @@ pub struct OracleMessage {
     pub block_height: u64,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct Commitment {
     pub public_key: Pubkey,
     pub args: ArgsCommitment
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Template of the encrypted payload, only checked off-chain by the holder of the
+/// commitment key since the indexer can't decrypt it.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct ArgsCommitment {
     pub fixed_string: String,
     pub string: String
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct CommitmentMessage {
     pub public_key: Pubkey,
     pub args: Vec<u8>
 }
 
+impl Commitment {
+    pub fn validate(&self) -> Option<Flaw> {
+        if PublicKey::from_slice(&self.public_key).is_err() {
+            return Some(Flaw::PubkeyInvalid);
+        }
+
+        None
+    }
+}
+
 /// Transfer
 /// Asset: This is a block:tx reference to the contract where the asset was created
 /// Output index of output to receive asset
 /// Amount: value assigning shares of the transfer to the appropriate UTXO output
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct TxTypeTransfer {
     pub asset: BlockTxTuple,
@@ -136,21 +207,38 @@ pub struct TxTypeTransfer {
     pub amount: U128,
 }
 
+/// NftTransfer
+/// Asset: block:tx of the nft collection
+/// Serial: token of the collection, numbered from 1 in mint order
+/// Output index of output to receive the token
+/// Sale price: sats paid for the token, the royalty of the collection is due on it
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftTransfer {
+    pub asset: BlockTxTuple,
+    pub serial: u64,
+    pub output: u32,
+    pub sale_price: Option<U128>,
+}
+
 // TxTypes: Transfer, ContractCreation, ContractCall
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct Transfer {
     pub transfers: Vec<TxTypeTransfer>,
+    pub nft_transfers: Option<Vec<NftTransfer>>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct ContractCreation {
     pub contract_type: ContractType,
     pub spec: Option<BlockTxTuple>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct ContractCall {
     pub contract: Option<BlockTxTuple>,
@@ -158,7 +246,7 @@ pub struct ContractCall {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct OpReturnMessage {
     pub transfer: Option<Transfer>,
     pub contract_creation: Option<ContractCreation>,
@@ -171,6 +259,21 @@ pub trait ContractValidator {
 
 impl ContractValidator for CallType {
     fn validate(&self) -> Option<Flaw> {
+        if let CallType::Batch(contract_calls) = self {
+            if contract_calls.is_empty() {
+                return Some(Flaw::InvalidBatchCall);
+            }
+
+            for contract_call in contract_calls {
+                if matches!(contract_call.call_type, CallType::Batch(_)) {
+                    return Some(Flaw::InvalidBatchCall);
+                }
+                if let Some(flaw) = contract_call.call_type.validate() {
+                    return Some(flaw);
+                }
+            }
+        }
+
         None
     }
 }
@@ -211,14 +314,16 @@ impl OpReturnMessage {
             break;
         }
 
+        // a glittr OP_RETURN takes precedence over the envelopes of the inputs
         if payload.is_empty() {
-            return Err(Flaw::NonGlittrMessage);
+            payload = OpReturnMessage::envelope_payload(tx)?;
         }
 
-        let message =
-            OpReturnMessage::deserialize(&mut Deserializer::from_slice(payload.as_slice()));
+        if payload.is_empty() {
+            return Err(Flaw::NonGlittrMessage);
+        }
 
-        match message {
+        match OpReturnMessage::decode_payload(&payload) {
             Ok(message) => {
                 if message.contract_call.is_none()
                     && message.contract_creation.is_none()
@@ -232,17 +337,112 @@ impl OpReturnMessage {
         }
     }
 
+    /// Payload revealed in the leaf script of a taproot script path spend, as
+    /// `OP_FALSE OP_IF "GLITTR" <payload pushes> OP_ENDIF`. Only the first envelope of the first
+    /// input carrying one is read.
+    fn envelope_payload(tx: &Transaction) -> Result<Vec<u8>, Flaw> {
+        for input in tx.input.iter() {
+            let Some(tapscript) = input.witness.tapscript() else {
+                continue;
+            };
+            let Ok(instructions) = tapscript.instructions().collect::<Result<Vec<_>, _>>() else {
+                continue;
+            };
+
+            let envelope_start = instructions.windows(3).position(|window| {
+                matches!(
+                    window,
+                    [
+                        Instruction::PushBytes(op_false),
+                        Instruction::Op(op_if),
+                        Instruction::PushBytes(magic_prefix),
+                    ] if op_false.is_empty()
+                        && *op_if == opcodes::all::OP_IF
+                        && magic_prefix.as_bytes() == OP_RETURN_MAGIC_PREFIX.as_bytes()
+                )
+            });
+            let Some(envelope_start) = envelope_start else {
+                continue;
+            };
+
+            let mut payload = Vec::new();
+            for instruction in &instructions[envelope_start + 3..] {
+                match instruction {
+                    Instruction::PushBytes(push) => {
+                        payload.extend_from_slice(push.as_bytes());
+                    }
+                    Instruction::Op(op) if *op == opcodes::all::OP_ENDIF => {
+                        return Ok(payload);
+                    }
+                    Instruction::Op(op) => {
+                        return Err(Flaw::InvalidInstruction(op.to_string()));
+                    }
+                }
+            }
+
+            return Err(Flaw::InvalidScript);
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// The first byte of the payload selects the format, any other byte is read as the legacy
+    /// JSON message.
+    fn decode_payload(payload: &[u8]) -> Result<OpReturnMessage, Flaw> {
+        match payload[0] {
+            OP_RETURN_FORMAT_BORSH => OpReturnMessage::try_from_slice(&payload[1..])
+                .map_err(|_| Flaw::FailedDeserialization),
+            OP_RETURN_FORMAT_BORSH_DEFLATE => {
+                let inflated =
+                    decompress_to_vec_with_limit(&payload[1..], MAX_INFLATED_PAYLOAD_SIZE)
+                        .map_err(|_| Flaw::FailedDeserialization)?;
+                OpReturnMessage::try_from_slice(&inflated).map_err(|_| Flaw::FailedDeserialization)
+            }
+            _ => <OpReturnMessage as Deserialize>::deserialize(&mut Deserializer::from_slice(
+                payload,
+            ))
+            .map_err(|_| Flaw::FailedDeserialization),
+        }
+    }
+
+    /// Borsh encoding of the message, deflated when it is shorter. The borsh layout of the
+    /// message types is part of the format, a layout change needs a new format byte.
+    pub fn encode_payload(&self) -> Vec<u8> {
+        let encoded = borsh::to_vec(self).unwrap();
+        let deflated = compress_to_vec(&encoded, 10);
+
+        let (format, body) = if deflated.len() < encoded.len() {
+            (OP_RETURN_FORMAT_BORSH_DEFLATE, deflated)
+        } else {
+            (OP_RETURN_FORMAT_BORSH, encoded)
+        };
+
+        let mut payload = Vec::with_capacity(body.len() + 1);
+        payload.push(format);
+        payload.extend_from_slice(&body);
+        payload
+    }
+
     pub fn validate(&self) -> Option<Flaw> {
         if let Some(contract_creation) = &self.contract_creation {
-            return match &contract_creation.contract_type {
+            let flaw = match &contract_creation.contract_type {
                 ContractType::Moa(mint_only_asset_contract) => mint_only_asset_contract.validate(),
                 ContractType::Mba(mint_burn_asset_contract) => mint_burn_asset_contract.validate(),
                 ContractType::Spec(spec_contract) => spec_contract.validate(),
                 ContractType::Nft(nft_asset_contract) => nft_asset_contract.validate()
             };
+            if flaw.is_some() {
+                return flaw;
+            }
         }
 
         if let Some(contract_call) = &self.contract_call {
+            // the calls of a batch name their own contracts
+            if matches!(contract_call.call_type, CallType::Batch(_))
+                && contract_call.contract.is_some()
+            {
+                return Some(Flaw::InvalidBatchCall);
+            }
             return contract_call.call_type.validate();
         }
 
@@ -252,14 +452,33 @@ impl OpReturnMessage {
     pub fn into_script(&self) -> ScriptBuf {
         let mut builder = script::Builder::new().push_opcode(opcodes::all::OP_RETURN);
         let magic_prefix: &PushBytes = OP_RETURN_MAGIC_PREFIX.as_bytes().try_into().unwrap();
-        let binding = self.to_string();
-        let script_bytes: &PushBytes = binding.as_bytes().try_into().unwrap();
+        let binding = self.encode_payload();
+        let script_bytes: &PushBytes = binding.as_slice().try_into().unwrap();
 
         builder = builder.push_slice(magic_prefix);
         builder = builder.push_slice(script_bytes);
 
         builder.into_script()
     }
+
+    /// Leaf script revealing the message in a taproot script path spend, for the messages that
+    /// don't fit in an OP_RETURN. The envelope is never executed, the key signs the spend.
+    pub fn into_envelope_script(&self, pubkey: &XOnlyPublicKey) -> ScriptBuf {
+        let mut builder = script::Builder::new()
+            .push_x_only_key(pubkey)
+            .push_opcode(opcodes::all::OP_CHECKSIG)
+            .push_opcode(opcodes::OP_FALSE)
+            .push_opcode(opcodes::all::OP_IF);
+        let magic_prefix: &PushBytes = OP_RETURN_MAGIC_PREFIX.as_bytes().try_into().unwrap();
+        builder = builder.push_slice(magic_prefix);
+
+        for chunk in self.encode_payload().chunks(MAX_SCRIPT_ELEMENT_SIZE) {
+            let chunk: &PushBytes = chunk.try_into().unwrap();
+            builder = builder.push_slice(chunk);
+        }
+
+        builder.push_opcode(opcodes::all::OP_ENDIF).into_script()
+    }
 }
 
 impl fmt::Display for OpReturnMessage {
@@ -271,19 +490,27 @@ impl fmt::Display for OpReturnMessage {
 #[cfg(test)]
 mod test {
     use bitcoin::consensus::deserialize;
-    use bitcoin::{locktime, transaction::Version, Amount, Transaction, TxOut};
+    use bitcoin::key::{Keypair, Secp256k1};
+    use bitcoin::secp256k1::SecretKey;
+    use bitcoin::{locktime, transaction::Version, Amount, Transaction, TxIn, TxOut, Witness};
     use bitcoincore_rpc::RawTx;
 
     use crate::transaction::message::ContractType;
     use crate::transaction::mint_only_asset::MintOnlyAssetContract;
     use crate::U128;
 
+    use super::mint_burn_asset::{
+        AccountType, BurnMechanisms, Collateralized, MBAMintMechanisms, MintBurnAssetContract,
+        MintStructure, SwapMechanisms,
+    };
     use super::mint_only_asset::MOAMintMechanisms;
-    use super::transaction_shared::FreeMint;
-    use super::{ContractCreation, OpReturnMessage};
+    use super::nft::{NftAssetContract, NftRoyalty, NftTokenMetadata};
+    use super::spec::{MintOnlyAssetSpec, SpecContract, SpecContractType};
+    use super::transaction_shared::{FreeMint, InputAsset, RatioType};
+    use super::*;
 
-    fn create_dummy_tx() -> Transaction {
-        let dummy_message = OpReturnMessage {
+    fn create_dummy_message() -> OpReturnMessage {
+        OpReturnMessage {
             transfer: None,
             contract_creation: Some(ContractCreation {
                 contract_type: ContractType::Moa(MintOnlyAssetContract {
@@ -305,19 +532,13 @@ mod test {
                 spec: None,
             }),
             contract_call: None,
-        };
-
-        Transaction {
-            input: Vec::new(),
-            lock_time: locktime::absolute::LockTime::ZERO,
-            output: vec![TxOut {
-                script_pubkey: dummy_message.into_script(),
-                value: Amount::from_int_btc(0),
-            }],
-            version: Version(2),
         }
     }
 
+    fn create_dummy_tx() -> Transaction {
+        message_tx(create_dummy_message().into_script())
+    }
+
     #[test]
     pub fn parse_op_return_message_success() {
         let tx = create_dummy_tx();
@@ -351,4 +572,378 @@ mod test {
 
         assert!(op_return_message.is_ok());
     }
+
+    fn message_tx(script_pubkey: ScriptBuf) -> Transaction {
+        Transaction {
+            input: Vec::new(),
+            lock_time: locktime::absolute::LockTime::ZERO,
+            output: vec![TxOut {
+                script_pubkey,
+                value: Amount::from_int_btc(0),
+            }],
+            version: Version(2),
+        }
+    }
+
+    fn contract_call(call_type: CallType) -> OpReturnMessage {
+        OpReturnMessage {
+            transfer: None,
+            contract_creation: None,
+            contract_call: Some(ContractCall {
+                contract: Some((840_000, 12)),
+                call_type,
+            }),
+        }
+    }
+
+    fn contract_creation(contract_type: ContractType) -> OpReturnMessage {
+        OpReturnMessage {
+            transfer: None,
+            contract_creation: Some(ContractCreation {
+                contract_type,
+                spec: None,
+            }),
+            contract_call: None,
+        }
+    }
+
+    fn oracle_message() -> OracleMessageSigned {
+        OracleMessageSigned {
+            signature: vec![7; 64],
+            message: OracleMessage {
+                input_outpoint: Some(OutPoint::null()),
+                min_in_value: None,
+                out_value: Some(U128(50_000)),
+                asset_id: None,
+                ratio: None,
+                ltv: Some((5, 10)),
+                outstanding: Some(U128(50_000)),
+                block_height: 840_000,
+            },
+        }
+    }
+
+    fn mint_burn_option() -> MintBurnOption {
+        MintBurnOption {
+            pointer: Some(1),
+            oracle_message: None,
+            pointer_to_key: None,
+            assert_values: None,
+            commitment_message: None,
+        }
+    }
+
+    /// A message of every contract type and call type with the size of its compact payload.
+    fn sample_messages() -> Vec<(OpReturnMessage, usize)> {
+        vec![
+            (
+                OpReturnMessage {
+                    transfer: Some(Transfer {
+                        transfers: vec![TxTypeTransfer {
+                            asset: (840_000, 12),
+                            output: 1,
+                            amount: U128(1_000),
+                        }],
+                        nft_transfers: None,
+                    }),
+                    contract_creation: None,
+                    contract_call: None,
+                },
+                21,
+            ),
+            (create_dummy_message(), 31),
+            (
+                contract_creation(ContractType::Mba(MintBurnAssetContract {
+                    ticker: Some("USD".to_string()),
+                    supply_cap: Some(U128(1_000_000)),
+                    divisibility: 18,
+                    live_time: 0,
+                    end_time: None,
+                    mint_mechanism: MBAMintMechanisms {
+                        preallocated: None,
+                        free_mint: None,
+                        purchase: None,
+                        collateralized: Some(Collateralized {
+                            input_assets: vec![InputAsset::GlittrAsset((840_000, 12))],
+                            _mutable_assets: false,
+                            mint_structure: MintStructure::Account(AccountType {
+                                max_ltv: (7, 10),
+                                ratio: RatioType::Fixed { ratio: (1, 1) },
+                                liquidation: None,
+                            }),
+                        }),
+                    },
+                    burn_mechanism: BurnMechanisms {
+                        return_collateral: None,
+                    },
+                    swap_mechanism: SwapMechanisms { fee: None },
+                    commitment: None,
+                })),
+                52,
+            ),
+            (
+                contract_creation(ContractType::Spec(SpecContract {
+                    spec: SpecContractType::MintOnlyAsset(MintOnlyAssetSpec {
+                        input_asset: Some(InputAsset::RawBtc),
+                        peg_in_type: None,
+                    }),
+                    pointer: Some(1),
+                    block_tx: None,
+                })),
+                14,
+            ),
+            (
+                contract_creation(ContractType::Nft(NftAssetContract {
+                    asset: vec![1; 32],
+                    supply_cap: Some(U128(100)),
+                    live_time: 0,
+                    end_time: None,
+                    pointer: Some(1),
+                    allowlist: None,
+                    mint_limit_per_address: None,
+                    price: None,
+                    royalty: None,
+                })),
+                28,
+            ),
+            (contract_call(CallType::Mint(mint_burn_option())), 18),
+            (contract_call(CallType::Burn(mint_burn_option())), 19),
+            (
+                contract_call(CallType::Swap(SwapOption {
+                    pointer: 1,
+                    assert_values: None,
+                })),
+                18,
+            ),
+            (
+                contract_call(CallType::OpenAccount(OpenAccountOption {
+                    pointer_to_key: 1,
+                    share_amount: U128(100_000),
+                })),
+                23,
+            ),
+            (
+                contract_call(CallType::CloseAccount(CloseAccountOption { pointer: 1 })),
+                18,
+            ),
+            (
+                contract_call(CallType::UpdateNft(UpdateNftOption {
+                    whitelist_address_bloom_filter: None,
+                    trusted_marketplace_fee_addresses: None,
+                    access_key_pointer: Some(1),
+                })),
+                20,
+            ),
+            (
+                contract_call(CallType::MintNft(MintNftOption {
+                    pointer: 1,
+                    metadata: NftTokenMetadata {
+                        content: None,
+                        uri: Some("ipfs://token".to_string()),
+                        attributes: None,
+                    },
+                })),
+                33,
+            ),
+            (
+                contract_call(CallType::AddCollateral(AddCollateralOption {
+                    pointer_to_key: 1,
+                    oracle_message: None,
+                })),
+                18,
+            ),
+            (
+                contract_call(CallType::Repay(RepayOption {
+                    pointer: 1,
+                    pointer_to_key: 2,
+                    oracle_message: None,
+                })),
+                20,
+            ),
+            (
+                contract_call(CallType::WithdrawCollateral(WithdrawCollateralOption {
+                    pointer: 1,
+                    pointer_to_key: 2,
+                    amounts: vec![((840_000, 12), U128(40_000))],
+                    oracle_message: None,
+                })),
+                36,
+            ),
+            (
+                contract_call(CallType::Liquidate(LiquidateOption {
+                    pointer: 1,
+                    oracle_message: oracle_message(),
+                })),
+                56,
+            ),
+            (
+                batch_call(vec![
+                    CallType::Swap(SwapOption {
+                        pointer: 1,
+                        assert_values: None,
+                    }),
+                    CallType::OpenAccount(OpenAccountOption {
+                        pointer_to_key: 1,
+                        share_amount: U128(100),
+                    }),
+                ]),
+                37,
+            ),
+        ]
+    }
+
+    fn batch_call(call_types: Vec<CallType>) -> OpReturnMessage {
+        OpReturnMessage {
+            transfer: None,
+            contract_creation: None,
+            contract_call: Some(ContractCall {
+                contract: None,
+                call_type: CallType::Batch(
+                    call_types
+                        .into_iter()
+                        .map(|call_type| contract_call(call_type).contract_call.unwrap())
+                        .collect(),
+                ),
+            }),
+        }
+    }
+
+    #[test]
+    pub fn validate_batch_call() {
+        let mint = || CallType::Mint(mint_burn_option());
+
+        assert!(batch_call(vec![mint(), mint()]).validate().is_none());
+        assert_eq!(batch_call(vec![]).validate(), Some(Flaw::InvalidBatchCall));
+
+        let nested = batch_call(vec![mint()]).contract_call.unwrap().call_type;
+        assert_eq!(batch_call(vec![mint(), nested]).validate(), Some(Flaw::InvalidBatchCall));
+
+        let mut message = batch_call(vec![mint()]);
+        message.contract_call.as_mut().unwrap().contract = Some((840_000, 12));
+        assert_eq!(message.validate(), Some(Flaw::InvalidBatchCall));
+    }
+
+    #[test]
+    pub fn validate_nft_royalty_fraction() {
+        for fraction in [(1, 0), (0, 0), (11, 10)] {
+            let message = contract_creation(ContractType::Nft(NftAssetContract {
+                asset: vec![1; 32],
+                supply_cap: None,
+                live_time: 0,
+                end_time: None,
+                pointer: None,
+                allowlist: None,
+                mint_limit_per_address: None,
+                price: None,
+                royalty: Some(NftRoyalty {
+                    fraction,
+                    pay_to_key: vec![2; 33],
+                }),
+            }));
+            assert_eq!(message.validate(), Some(Flaw::FractionInvalid));
+        }
+    }
+
+    #[test]
+    pub fn compact_payload_round_trip() {
+        for (message, _) in sample_messages() {
+            let parsed = OpReturnMessage::parse_tx(&message_tx(message.into_script())).unwrap();
+
+            assert_eq!(borsh::to_vec(&parsed).unwrap(), borsh::to_vec(&message).unwrap());
+        }
+    }
+
+    #[test]
+    pub fn compact_payload_size() {
+        for (message, size) in sample_messages() {
+            let payload = message.encode_payload();
+
+            assert_eq!(payload.len(), size, "{}", message);
+            assert!(payload.len() < message.to_string().len(), "{}", message);
+            // OP_RETURN, the magic prefix and the two pushes fit in 80 bytes
+            assert!(payload.len() + 9 <= 80, "{}", message);
+        }
+    }
+
+    #[test]
+    pub fn parse_legacy_json_payload() {
+        let message = create_dummy_message();
+        let script_pubkey = script::Builder::new()
+            .push_opcode(opcodes::all::OP_RETURN)
+            .push_slice(<&PushBytes>::try_from(OP_RETURN_MAGIC_PREFIX.as_bytes()).unwrap())
+            .push_slice(<&PushBytes>::try_from(message.to_string().as_bytes()).unwrap())
+            .into_script();
+
+        let parsed = OpReturnMessage::parse_tx(&message_tx(script_pubkey)).unwrap();
+
+        assert_eq!(borsh::to_vec(&parsed).unwrap(), borsh::to_vec(&message).unwrap());
+    }
+
+    /// Reveals the message in the witness of the first input, the control block is not checked.
+    fn envelope_tx(message: &OpReturnMessage, script_pubkey: ScriptBuf) -> Transaction {
+        let keypair = Keypair::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&[1; 32]).unwrap(),
+        );
+        let (pubkey, _) = keypair.x_only_public_key();
+        let mut control_block = vec![0xc0];
+        control_block.extend_from_slice(&pubkey.serialize());
+
+        let mut tx = message_tx(script_pubkey);
+        tx.input.push(TxIn {
+            witness: Witness::from_slice(&[
+                vec![0; 64],
+                message.into_envelope_script(&pubkey).into_bytes(),
+                control_block,
+            ]),
+            ..Default::default()
+        });
+        tx
+    }
+
+    fn large_message() -> OpReturnMessage {
+        contract_creation(ContractType::Nft(NftAssetContract {
+            asset: (0..2_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8).collect(),
+            supply_cap: Some(U128(1)),
+            live_time: 0,
+            end_time: None,
+            pointer: Some(1),
+            allowlist: None,
+            mint_limit_per_address: None,
+            price: None,
+            royalty: None,
+        }))
+    }
+
+    #[test]
+    pub fn parse_envelope_message() {
+        let message = large_message();
+        assert!(message.encode_payload().len() > MAX_SCRIPT_ELEMENT_SIZE);
+
+        let tx = envelope_tx(&message, ScriptBuf::new());
+        let parsed = OpReturnMessage::parse_tx(&tx).unwrap();
+
+        assert_eq!(borsh::to_vec(&parsed).unwrap(), borsh::to_vec(&message).unwrap());
+    }
+
+    #[test]
+    pub fn parse_op_return_before_envelope() {
+        let message = create_dummy_message();
+        let tx = envelope_tx(&large_message(), message.into_script());
+
+        let parsed = OpReturnMessage::parse_tx(&tx).unwrap();
+
+        assert_eq!(borsh::to_vec(&parsed).unwrap(), borsh::to_vec(&message).unwrap());
+    }
+
+    #[test]
+    pub fn parse_unterminated_envelope() {
+        let mut tx = envelope_tx(&create_dummy_message(), ScriptBuf::new());
+        let mut witness: Vec<Vec<u8>> = tx.input[0].witness.to_vec();
+        // drop the OP_ENDIF
+        witness[1].pop();
+        tx.input[0].witness = Witness::from_slice(&witness);
+
+        assert_eq!(OpReturnMessage::parse_tx(&tx).unwrap_err(), Flaw::InvalidScript);
+    }
 }
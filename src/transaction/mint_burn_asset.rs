@@ -1,9 +1,10 @@
 use super::*;
+use bitcoin::PublicKey;
 use message::{Commitment, ContractValidator};
 use transaction_shared::{FreeMint, InputAsset, OracleSetting, Preallocated, PurchaseBurnSwap, RatioType};
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MintBurnAssetContract {
     pub ticker: Option<String>,
@@ -18,7 +19,7 @@ pub struct MintBurnAssetContract {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MBAMintMechanisms {
     pub preallocated: Option<Preallocated>,
@@ -28,20 +29,23 @@ pub struct MBAMintMechanisms {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct BurnMechanisms {
     pub return_collateral: Option<ReturnCollateral>,
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct SwapMechanisms {
+    // in basis points of the input amount, retained in the pool reserves
     pub fee: Option<U128>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+pub const SWAP_FEE_DENOMINATOR: u128 = 10_000;
+
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct Collateralized {
     pub input_assets: Vec<InputAsset>,
@@ -49,7 +53,7 @@ pub struct Collateralized {
     pub mint_structure: MintStructure,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum MintStructure {
     Ratio(RatioType),
@@ -57,14 +61,25 @@ pub enum MintStructure {
     Account(AccountType),
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct AccountType {
     pub max_ltv: Fraction,
     pub ratio: RatioType,
+    pub liquidation: Option<Liquidation>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+/// Accounts signed by the oracle above the `threshold` ltv can be liquidated by anyone repaying
+/// their outstanding amount, the liquidator takes the collateral worth the repaid amount plus
+/// the `bonus`.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub struct Liquidation {
+    pub threshold: Fraction,
+    pub bonus: Option<Fraction>,
+}
+
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub struct ProportionalType {
     pub ratio_model: RatioModel,
@@ -72,18 +87,21 @@ pub struct ProportionalType {
     pub inital_mint_pointer_to_key: Option<u32>, 
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum RatioModel {
     ConstantProduct,
     ConstantSum, // New variant for CSMM
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct ReturnCollateral {
-    pub fee: Option<Fraction>, // TODO: fee
+    // of the returned amount, stays in the contract unless fee_pay_to_key is set
+    pub fee: Option<Fraction>,
     pub oracle_setting: Option<OracleSetting>,
+    // the fee is allocated to the output paying to the key
+    pub fee_pay_to_key: Option<Pubkey>,
 }
 
 impl ContractValidator for Collateralized {
@@ -114,6 +132,11 @@ impl ContractValidator for Collateralized {
                 if account.max_ltv.0 > account.max_ltv.1 {
                     return Some(Flaw::FractionInvalid);
                 }
+                if let Some(liquidation) = &account.liquidation {
+                    if let Some(flaw) = liquidation.validate(account) {
+                        return Some(flaw);
+                    }
+                }
                 return account.ratio.validate();
             }
         }
@@ -122,15 +145,86 @@ impl ContractValidator for Collateralized {
     }
 }
 
+impl Liquidation {
+    pub fn validate(&self, account: &AccountType) -> Option<Flaw> {
+        // only the oracle can tell the ltv of an account changed
+        if !matches!(account.ratio, RatioType::Oracle { .. }) {
+            return Some(Flaw::LiquidationNotAllowed);
+        }
+
+        // accounts at max ltv can't be liquidated right away
+        let (threshold, max_ltv) = (self.threshold, account.max_ltv);
+        if threshold.1 == 0
+            || (threshold.0 as u128) * (max_ltv.1 as u128)
+                < (max_ltv.0 as u128) * (threshold.1 as u128)
+        {
+            return Some(Flaw::FractionInvalid);
+        }
+
+        if let Some(bonus) = self.bonus {
+            if bonus.1 == 0 {
+                return Some(Flaw::FractionInvalid);
+            }
+        }
+        None
+    }
+
+    /// Collateral paid for `collateral_value`, the bonus on top of it.
+    pub fn seized_amount(&self, collateral_value: u128) -> u128 {
+        match self.bonus {
+            Some((numerator, denominator)) if denominator > 0 => collateral_value.saturating_add(
+                collateral_value.saturating_mul(numerator as u128) / denominator as u128,
+            ),
+            _ => collateral_value,
+        }
+    }
+}
+
 impl ReturnCollateral {
     pub fn validate(&self) -> Option<Flaw> {
         if let Some(fee) = self.fee {
-            if fee.0 > fee.1 {
+            if fee.1 == 0 || fee.0 > fee.1 {
                 return Some(Flaw::FractionInvalid);
             }
         }
+        if let Some(fee_pay_to_key) = &self.fee_pay_to_key {
+            if PublicKey::from_slice(fee_pay_to_key).is_err() {
+                return Some(Flaw::PubkeyInvalid);
+            }
+        }
         None
     }
+
+    /// The fee is rounded up so that small burns can't skip it.
+    pub fn fee_amount(&self, amount: u128) -> u128 {
+        match self.fee {
+            Some((numerator, denominator)) if denominator > 0 => amount
+                .saturating_mul(numerator as u128)
+                .div_ceil(denominator as u128)
+                .min(amount),
+            _ => 0,
+        }
+    }
+}
+
+impl SwapMechanisms {
+    pub fn validate(&self) -> Option<Flaw> {
+        if let Some(fee) = &self.fee {
+            // a fee of the whole input leaves nothing to swap
+            if fee.0 >= SWAP_FEE_DENOMINATOR {
+                return Some(Flaw::SwapFeeInvalid);
+            }
+        }
+        None
+    }
+
+    /// The fee is rounded up so that small swaps can't skip it.
+    pub fn fee_amount(&self, input_amount: u128) -> u128 {
+        match &self.fee {
+            Some(fee) => input_amount.saturating_mul(fee.0).div_ceil(SWAP_FEE_DENOMINATOR),
+            None => 0,
+        }
+    }
 }
 
 impl ContractValidator for MintBurnAssetContract {
@@ -145,6 +239,16 @@ impl ContractValidator for MintBurnAssetContract {
             return Some(Flaw::NotImplemented);
         }
 
+        if let Some(commitment) = &self.commitment {
+            if let Some(flaw) = commitment.validate() {
+                return Some(flaw);
+            }
+        }
+
+        if let Some(flaw) = self.swap_mechanism.validate() {
+            return Some(flaw);
+        }
+
         if let Some(preallocated) = &self.mint_mechanism.preallocated {
             return preallocated.validate(&message::ContractType::Mba(self.clone()));
         }
@@ -5,7 +5,7 @@ use transaction_shared::{FreeMint, Preallocated, PurchaseBurnSwap};
 use super::*;
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MOAMintMechanisms {
     pub preallocated: Option<Preallocated>,
@@ -14,7 +14,7 @@ pub struct MOAMintMechanisms {
 }
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MintOnlyAssetContract {
     pub ticker: Option<String>,
@@ -39,6 +39,12 @@ impl ContractValidator for MintOnlyAssetContract {
             return Some(Flaw::NotImplemented);
         }
 
+        if let Some(commitment) = &self.commitment {
+            if let Some(flaw) = commitment.validate() {
+                return Some(flaw);
+            }
+        }
+
         if let Some(preallocated) = &self.mint_mechanism.preallocated {
             return preallocated.validate(&message::ContractType::Moa(self.clone()));
         }
@@ -1,7 +1,8 @@
 use super::*;
+pub mod builder;
 pub mod message;
 pub mod mint_burn_asset;
 pub mod mint_only_asset;
-pub mod transaction_shared;
+pub mod nft;
 pub mod spec;
-pub mod nft;
\ No newline at end of file
+pub mod transaction_shared;
@@ -1,10 +1,13 @@
+use std::collections::HashMap;
+
+use bitcoin::PublicKey;
 use flaw::Flaw;
 use message::ContractValidator;
 
 use super::*;
 
 #[serde_with::skip_serializing_none]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct NftAssetContract {
     pub asset: Vec<u8>,
@@ -13,6 +16,49 @@ pub struct NftAssetContract {
     pub end_time: Option<RelativeOrAbsoluteBlockHeight>,
     // the target output index that holds the nft
     pub pointer: Option<u32>,
+    pub allowlist: Option<NftAllowlist>,
+    // mints by the same listed pubkey or bloom filter address, by the same output script
+    // without an allowlist
+    pub mint_limit_per_address: Option<u32>,
+    pub price: Option<NftPrice>,
+    pub royalty: Option<NftRoyalty>,
+}
+
+/// A mint is allowed when one of the inputs is signed by a listed pubkey
+/// or when the address receiving the nft is in the bloom filter.
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftAllowlist {
+    pub pubkeys: Option<Vec<Pubkey>>,
+    // compressed with `bloom_filter_to_compressed_vec`
+    pub bloom_filter: Option<Vec<u8>>,
+}
+
+/// Sats paid to the pay_to_key for every mint.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftPrice {
+    pub amount: U128,
+    pub pay_to_key: Pubkey,
+}
+
+/// Share of the sale price, paid in sats to the pay_to_key when a token is sold.
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftRoyalty {
+    pub fraction: Fraction,
+    pub pay_to_key: Pubkey,
+}
+
+/// Data of a single token of the collection, set when the token is minted.
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftTokenMetadata {
+    pub content: Option<Vec<u8>>,
+    pub uri: Option<String>,
+    pub attributes: Option<HashMap<String, String>>,
 }
 
 impl ContractValidator for NftAssetContract {
@@ -22,6 +68,37 @@ impl ContractValidator for NftAssetContract {
                 return Some(Flaw::EndTimeIsLessThanLiveTime);
             }
         }
+
+        if let Some(allowlist) = &self.allowlist {
+            for pubkey in allowlist.pubkeys.iter().flatten() {
+                if PublicKey::from_slice(pubkey).is_err() {
+                    return Some(Flaw::PubkeyInvalid);
+                }
+            }
+
+            if let Some(bloom_filter) = &allowlist.bloom_filter {
+                if try_compressed_vec_to_bloom_filter(bloom_filter.clone()).is_none() {
+                    return Some(Flaw::InvalidBloomFilter);
+                }
+            }
+        }
+
+        if let Some(price) = &self.price {
+            if PublicKey::from_slice(&price.pay_to_key).is_err() {
+                return Some(Flaw::PubkeyInvalid);
+            }
+        }
+
+        if let Some(royalty) = &self.royalty {
+            if royalty.fraction.1 == 0 || royalty.fraction.0 > royalty.fraction.1 {
+                return Some(Flaw::FractionInvalid);
+            }
+
+            if PublicKey::from_slice(&royalty.pay_to_key).is_err() {
+                return Some(Flaw::PubkeyInvalid);
+            }
+        }
+
         None
     }
 }
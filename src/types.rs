@@ -1,5 +1,10 @@
 use std::{error::Error, fmt, str::FromStr};
 
+use bitcoin::{hashes::Hash, OutPoint, Txid};
+use borsh::{
+    io::{Read, Result as IoResult, Write},
+    BorshDeserialize, BorshSerialize,
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug)]
@@ -57,7 +62,7 @@ impl FromStr for BlockTx {
 
 /// U128 is wrapped u128, represented as string when serialized
 /// This is because JSON only supports up to u32 as integer representation
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, BorshSerialize, BorshDeserialize)]
 pub struct U128(pub u128);
 
 impl Serialize for U128 {
@@ -80,3 +85,22 @@ impl<'de> Deserialize<'de> for U128 {
         })?))
     }
 }
+
+/// Borsh encoding of an `Option<OutPoint>` field, the txid bytes followed by the vout.
+pub mod borsh_option_outpoint {
+    use super::*;
+
+    pub fn serialize<W: Write>(outpoint: &Option<OutPoint>, writer: &mut W) -> IoResult<()> {
+        let outpoint = outpoint.map(|outpoint| (outpoint.txid.to_byte_array(), outpoint.vout));
+        BorshSerialize::serialize(&outpoint, writer)
+    }
+
+    pub fn deserialize<R: Read>(reader: &mut R) -> IoResult<Option<OutPoint>> {
+        let outpoint: Option<([u8; 32], u32)> = BorshDeserialize::deserialize_reader(reader)?;
+
+        Ok(outpoint.map(|(txid, vout)| OutPoint {
+            txid: Txid::from_byte_array(txid),
+            vout,
+        }))
+    }
+}
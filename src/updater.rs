@@ -1,15 +1,19 @@
 mod burn;
 mod collateralized;
 mod mint;
+mod nft_token;
+mod update_nft;
 mod updater_shared;
 
 use api::MintType;
-use collateralized::CollateralizedAssetData;
+pub use collateralized::{CollateralizedAssetData, ContractCollateralAccounts};
+pub use nft_token::{nft_token_key, NftToken, NftTokenId, NftTokens};
+pub use update_nft::NftContractData;
 pub use updater_shared::*;
 mod spec;
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     str::FromStr,
 };
 
@@ -23,17 +27,21 @@ use bitcoin::{
 };
 use database::{
     DatabaseError, ASSET_CONTRACT_DATA_PREFIX, ASSET_LIST_PREFIX, COLLATERALIZED_CONTRACT_DATA,
-    INDEXER_LAST_BLOCK_PREFIX, MESSAGE_PREFIX, STATE_KEYS_PREFIX, TICKER_TO_BLOCK_TX_PREFIX,
+    COLLATERAL_ACCOUNTS_PREFIX, COMMITMENT_PREFIX, INDEXER_LAST_BLOCK_PREFIX, MESSAGE_PREFIX,
+    NFT_TOKENS_PREFIX, SPEC_CONTRACT_OWNED_PREFIX, STATE_KEYS_PREFIX, TICKER_TO_BLOCK_TX_PREFIX,
     TRANSACTION_TO_BLOCK_TX_PREFIX, VESTING_CONTRACT_DATA_PREFIX,
 };
+use constants::{rules, RuleSet, Rules};
 use flaw::Flaw;
-use message::{CallType, ContractType, OpReturnMessage, TxTypeTransfer};
+use message::{
+    CallType, CommitmentMessage, ContractCall, ContractType, OpReturnMessage, TxTypeTransfer,
+};
 use mint_only_asset::MintOnlyAssetContract;
 use transaction_shared::{InputAsset, PurchaseBurnSwap, VestingPlan};
 
 use super::*;
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct AssetContractData {
     pub minted_supply: u128,
@@ -41,58 +49,75 @@ pub struct AssetContractData {
     pub burned_supply: u128,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct AssetList {
     pub list: HashMap<BlockTxString, u128>,
 }
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct MessageDataOutcome {
     pub message: Option<OpReturnMessage>,
     pub flaw: Option<Flaw>,
+    // flaw of each call of a batch call in order, the calls after a flawed call don't run
+    pub call_flaws: Option<Vec<Option<Flaw>>>,
 }
 pub struct PBSMintResult {
     pub out_value: u128,
     pub txout: u32,
 }
 
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
 #[serde(rename_all = "snake_case")]
 pub struct VestingContractData {
     pub claimed_allocations: HashMap<String, u128>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+#[derive(
+    Serialize, Deserialize, BorshSerialize, BorshDeserialize, Debug, Clone, Default, Eq, PartialEq,
+)]
 #[serde(rename_all = "snake_case")]
 pub struct CollateralAccounts {
     pub collateral_accounts: HashMap<BlockTxString, CollateralAccount>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, Hash, PartialEq)]
+#[derive(
+    Serialize,
+    Deserialize,
+    BorshSerialize,
+    BorshDeserialize,
+    Debug,
+    Clone,
+    Default,
+    Eq,
+    Hash,
+    PartialEq,
+)]
 pub struct CollateralAccount {
-    pub collateral_amounts: Vec<(BlockTxTuple, u128)>,
-    // TODO: remove total_collateral_amount
-    pub total_collateral_amount: u128,
-    pub ltv: Fraction, // ltv = total_amount_used / total_collateral_amount (in lending amount)
+    pub ltv: Fraction, // ltv = total_amount_used / share value (in lending amount)
     pub amount_outstanding: u128,
-    pub share_amount: u128, // TODO: implement
+    pub share_amounts: ShareAmounts, // shares of the collateral held by the contract
 }
 
+/// Shares of a collateral account by collateral asset.
+pub type ShareAmounts = BTreeMap<BlockTxString, u128>;
+
 // TODO: statekey should be general, could accept dynamic value for the key value
-#[derive(Serialize, Deserialize, Default, Eq, PartialEq, Debug)]
+#[derive(
+    Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Default, Eq, PartialEq, Debug,
+)]
 pub struct StateKeys {
     pub contract_ids: HashSet<BlockTxTuple>,
 }
 
-#[derive(Deserialize, Serialize, Default)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default)]
 #[serde(rename_all = "snake_case")]
 pub struct SpecContractOwned {
     pub specs: HashSet<BlockTxTuple>,
 }
 #[cfg(feature = "helper-api")]
-#[derive(Deserialize, Serialize, Clone, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
 pub struct UTXOBalances {
     pub txid: String,
     pub vout: u32,
@@ -100,20 +125,22 @@ pub struct UTXOBalances {
 }
 
 #[cfg(feature = "helper-api")]
-#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
 pub struct AddressAssetList {
     pub summarized: HashMap<BlockTxString, U128>,
     pub utxos: Vec<UTXOBalances>,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Allocation {
     asset_list: AssetList,
     spec_owned: SpecContractOwned,
     state_keys: StateKeys,
     collateral_accounts: CollateralAccounts,
+    nft_tokens: NftTokens,
 
-    helper_outpoint_collateral_accounts: HashMap<CollateralAccount, OutPoint>,
+    // outpoint of the collateral account by contract id
+    helper_outpoint_collateral_accounts: HashMap<BlockTxString, OutPoint>,
 }
 
 pub struct Updater {
@@ -122,6 +149,16 @@ pub struct Updater {
 
     unallocated_inputs: Allocation,
     allocated_outputs: HashMap<u32, Allocation>,
+
+    // outpoints holding any glittr state, only kept during the initial sync
+    glittr_outpoints: Option<HashSet<OutPoint>>,
+
+    // calls of the batch paid by the outputs of the transaction
+    paid_calls: u32,
+
+    rules: Rules,
+    // rule set of the block being indexed
+    rule_set: RuleSet,
 }
 
 impl Updater {
@@ -134,9 +171,21 @@ impl Updater {
 
             unallocated_inputs: Allocation::default(),
             allocated_outputs: HashMap::new(),
+
+            glittr_outpoints: None,
+
+            paid_calls: 0,
+
+            rules: rules(),
+            rule_set: RuleSet::default(),
         }
     }
 
+    /// Index with other rule sets than the ones of the network.
+    pub fn set_rules(&mut self, rules: Rules) {
+        self.rules = rules;
+    }
+
     pub async fn unallocate_inputs(&mut self, tx: &Transaction) -> Result<(), Box<dyn Error>> {
         for tx_input in tx.input.iter() {
             let outpoint = &OutPoint {
@@ -144,6 +193,10 @@ impl Updater {
                 vout: tx_input.previous_output.vout,
             };
 
+            if let Some(glittr_outpoints) = self.glittr_outpoints.as_mut() {
+                glittr_outpoints.remove(outpoint);
+            }
+
             // set asset_list
             if let Ok(asset_list) = self.get_asset_list(outpoint).await {
                 for asset in asset_list.list.iter() {
@@ -185,19 +238,33 @@ impl Updater {
 
             // set collateral_account
             if let Ok(collateral_accounts) = self.get_collateral_accounts(outpoint).await {
+                self.index_collateral_accounts(outpoint, &collateral_accounts, true)
+                    .await;
+
                 for (contract_id, collateral_account) in collateral_accounts.collateral_accounts {
                     self.unallocated_inputs
-                        .collateral_accounts
-                        .collateral_accounts
-                        .insert(contract_id, collateral_account.clone());
+                        .helper_outpoint_collateral_accounts
+                        .insert(contract_id.clone(), *outpoint);
 
                     self.unallocated_inputs
-                        .helper_outpoint_collateral_accounts
-                        .insert(collateral_account, *outpoint);
+                        .collateral_accounts
+                        .collateral_accounts
+                        .insert(contract_id, collateral_account);
                 }
 
                 self.delete_collateral_accounts(outpoint).await
             }
+
+            // set nft_tokens
+            if let Ok(nft_tokens) = self.get_nft_tokens(outpoint).await {
+                if !nft_tokens.tokens.is_empty() {
+                    self.unallocated_inputs
+                        .nft_tokens
+                        .tokens
+                        .extend(nft_tokens.tokens);
+                    self.delete_nft_tokens(outpoint).await
+                }
+            }
         }
 
         Ok(())
@@ -294,8 +361,20 @@ impl Updater {
                 )
                 .await;
             }
+
+            // nft tokens
+            let nft_tokens = &self.unallocated_inputs.nft_tokens.tokens.clone();
+            for token_id in nft_tokens {
+                self.move_nft_token_allocation(vout, token_id).await;
+            }
         } else {
             log::info!("No non op_return index, unallocated inputs are lost");
+
+            for token_id in self.unallocated_inputs.nft_tokens.tokens.iter() {
+                if let Err(flaw) = self.set_nft_token_owner(token_id, None).await {
+                    log::error!("Failed to update the nft token owner: {:?}", flaw);
+                }
+            }
         }
 
         for allocation in self.allocated_outputs.iter() {
@@ -304,6 +383,10 @@ impl Updater {
                 vout: *allocation.0,
             };
 
+            if let Some(glittr_outpoints) = self.glittr_outpoints.as_mut() {
+                glittr_outpoints.insert(*outpoint);
+            }
+
             self.set_asset_list(outpoint, &allocation.1.asset_list)
                 .await;
             self.set_spec_contract_owned(outpoint, &allocation.1.spec_owned)
@@ -319,6 +402,22 @@ impl Updater {
             {
                 self.set_collateral_accounts(outpoint, &allocation.1.collateral_accounts)
                     .await;
+                self.index_collateral_accounts(outpoint, &allocation.1.collateral_accounts, false)
+                    .await;
+            }
+
+            if !allocation.1.nft_tokens.tokens.is_empty() {
+                self.set_nft_tokens(outpoint, &allocation.1.nft_tokens)
+                    .await;
+
+                for token_id in allocation.1.nft_tokens.tokens.iter() {
+                    if let Err(flaw) = self
+                        .set_nft_token_owner(token_id, Some(outpoint.to_string()))
+                        .await
+                    {
+                        log::error!("Failed to update the nft token owner: {:?}", flaw);
+                    }
+                }
             }
         }
 
@@ -332,6 +431,50 @@ impl Updater {
         Ok(())
     }
 
+    /// Load every outpoint holding glittr state into memory, transactions that don't spend them
+    /// can be skipped until `untrack_glittr_outpoints` is called.
+    pub async fn track_glittr_outpoints(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut glittr_outpoints = HashSet::new();
+
+        let database = self.database.lock().await;
+        for prefix in [
+            ASSET_LIST_PREFIX,
+            SPEC_CONTRACT_OWNED_PREFIX,
+            STATE_KEYS_PREFIX,
+            COLLATERAL_ACCOUNTS_PREFIX,
+            NFT_TOKENS_PREFIX,
+        ] {
+            let keys = database
+                .expensive_find_keys_by_prefix(prefix)
+                .map_err(|_| "Failed to load the glittr outpoints")?;
+
+            for key in keys {
+                let outpoint = key.trim_start_matches(&format!("{}:", prefix)).to_string();
+                glittr_outpoints.insert(OutPoint::from_str(&outpoint)?);
+            }
+        }
+        drop(database);
+
+        self.glittr_outpoints = Some(glittr_outpoints);
+
+        Ok(())
+    }
+
+    pub fn untrack_glittr_outpoints(&mut self) {
+        self.glittr_outpoints = None;
+    }
+
+    /// Whether the transaction could spend glittr state, always true when the outpoints are not tracked.
+    pub fn may_spend_glittr_outpoint(&self, tx: &Transaction) -> bool {
+        match &self.glittr_outpoints {
+            Some(glittr_outpoints) => tx
+                .input
+                .iter()
+                .any(|input| glittr_outpoints.contains(&input.previous_output)),
+            None => true,
+        }
+    }
+
     fn is_op_return_index(&self, output: &TxOut) -> bool {
         let mut instructions = output.script_pubkey.instructions();
         if instructions.next() == Some(Ok(Instruction::Op(opcodes::all::OP_RETURN))) {
@@ -362,16 +505,32 @@ impl Updater {
         let mut outcome = MessageDataOutcome {
             message: None,
             flaw: None,
+            call_flaws: None,
         };
 
         let block_tx = &BlockTx {
             block: block_height,
             tx: tx_index,
         };
+        self.rule_set = self.rules.at(block_height);
+        let message_result = message_result.and_then(|message| self.apply_rule_set(message));
         let mut ticker: Option<String> = None;
 
         if let Ok(message) = message_result.clone() {
             outcome.message = Some(message.clone());
+
+            // a message with a batch call is all or nothing, a flaw reverts its allocations
+            let savepoint = match &message.contract_call {
+                Some(ContractCall {
+                    call_type: CallType::Batch(_),
+                    ..
+                }) => {
+                    self.database.lock().await.begin_savepoint();
+                    Some((self.unallocated_inputs.clone(), self.allocated_outputs.clone()))
+                }
+                _ => None,
+            };
+
             // NOTE: static validation
             if let Some(flaw) = message.validate() {
                 outcome.flaw = Some(flaw)
@@ -382,6 +541,12 @@ impl Updater {
                 if outcome.flaw.is_none() {
                     outcome.flaw = self.transfers(tx, transfer.transfers).await;
                 }
+
+                if let Some(nft_transfers) = transfer.nft_transfers {
+                    if outcome.flaw.is_none() {
+                        outcome.flaw = self.nft_transfers(tx, nft_transfers).await;
+                    }
+                }
             }
 
             if let Some(contract_creation) = message.contract_creation {
@@ -520,61 +685,42 @@ impl Updater {
             }
 
             if let Some(contract_call) = message.contract_call {
-                let (message, contract_id) = match contract_call.contract {
-                    Some(contract_id) => (self.get_message(&contract_id).await, contract_id),
-                    None => (message_result, block_tx.to_tuple()),
-                };
-
-                match contract_call.call_type {
-                    CallType::Mint(mint_option) => {
-                        if outcome.flaw.is_none() {
-                            outcome.flaw = self
-                                .mint(tx, block_tx, &contract_id, &mint_option, message)
-                                .await;
-                        }
-                    }
-                    CallType::Burn(burn_option) => {
-                        if outcome.flaw.is_none() {
-                            outcome.flaw = self
-                                .burn(tx, block_tx, &contract_id, &burn_option, message)
-                                .await;
-                        }
-                    }
-                    CallType::Swap(swap_option) => {
-                        if outcome.flaw.is_none() {
-                            outcome.flaw = self
-                                .process_swap(tx, block_tx, &contract_id, &swap_option, message)
-                                .await;
-                        }
-                    }
-                    CallType::OpenAccount(open_account_option) => {
-                        if outcome.flaw.is_none() {
-                            outcome.flaw = self
-                                .process_open_account(
-                                    tx,
-                                    block_tx,
-                                    &contract_id,
-                                    &open_account_option,
-                                    message,
-                                )
-                                .await;
-                        }
-                    }
-                    CallType::CloseAccount(close_account_option) => {
-                        if outcome.flaw.is_none() {
-                            outcome.flaw = self
-                                .process_close_account(
-                                    tx,
-                                    block_tx,
-                                    &contract_id,
-                                    &close_account_option,
-                                )
+                if let CallType::Batch(contract_calls) = contract_call.call_type {
+                    if outcome.flaw.is_none() {
+                        let mut call_flaws = Vec::new();
+                        self.paid_calls = 0;
+                        for contract_call in contract_calls {
+                            let mut flaw = self
+                                .process_contract_call(tx, block_tx, contract_call, &message_result)
                                 .await;
+                            // every paid call reads the same outputs, they pay one call only
+                            if flaw.is_none() && self.paid_calls > 1 {
+                                flaw = Some(Flaw::BatchPaymentReused);
+                            }
+                            call_flaws.push(flaw.clone());
+
+                            if flaw.is_some() {
+                                outcome.flaw = flaw;
+                                break;
+                            }
                         }
+                        outcome.call_flaws = Some(call_flaws);
                     }
-                    CallType::UpdateNft(_update_nft_option) => {
-                        // TODO update nft
-                    }
+                } else if outcome.flaw.is_none() {
+                    outcome.flaw = self
+                        .process_contract_call(tx, block_tx, contract_call, &message_result)
+                        .await;
+                }
+            }
+
+            if let Some((unallocated_inputs, allocated_outputs)) = savepoint {
+                if outcome.flaw.is_some() {
+                    self.unallocated_inputs = unallocated_inputs;
+                    self.allocated_outputs = allocated_outputs;
+                    self.database.lock().await.rollback_savepoint();
+                    ticker = None;
+                } else {
+                    self.database.lock().await.release_savepoint();
                 }
             }
         } else {
@@ -582,12 +728,15 @@ impl Updater {
         }
 
         if !self.is_read_only {
-            log::info!(
-                "# Outcome {:?}, {:?} at {}",
-                outcome.flaw,
-                outcome.message,
-                block_tx
-            );
+            // the pending transactions are evaluated on an overlay at every mempool update
+            if !self.database.lock().await.is_snapshot() {
+                log::info!(
+                    "# Outcome {:?}, {:?} at {}",
+                    outcome.flaw,
+                    outcome.message,
+                    block_tx
+                );
+            }
             self.database.lock().await.put(
                 MESSAGE_PREFIX,
                 block_tx.to_string().as_str(),
@@ -612,6 +761,143 @@ impl Updater {
         Ok(outcome)
     }
 
+    /// The fields and calls added by a rule set are unknown before its activation, the fields
+    /// are dropped and the calls fail to parse like they did before.
+    fn apply_rule_set(&self, mut message: OpReturnMessage) -> Result<OpReturnMessage, Flaw> {
+        if let Some(transfer) = &mut message.transfer {
+            if !self.rule_set.nft_tokens {
+                transfer.nft_transfers = None;
+            }
+        }
+
+        if let Some(contract_creation) = &mut message.contract_creation {
+            match &mut contract_creation.contract_type {
+                ContractType::Nft(nft) => {
+                    if !self.rule_set.nft_mint_rules {
+                        nft.allowlist = None;
+                        nft.mint_limit_per_address = None;
+                        nft.price = None;
+                    }
+                    if !self.rule_set.nft_royalties {
+                        nft.royalty = None;
+                    }
+                }
+                ContractType::Mba(mba) => {
+                    if let Some(collateralized) = &mut mba.mint_mechanism.collateralized {
+                        if let mint_burn_asset::MintStructure::Account(account_type) =
+                            &mut collateralized.mint_structure
+                        {
+                            if !self.rule_set.liquidation {
+                                account_type.liquidation = None;
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(contract_call) = &message.contract_call {
+            if !self.is_call_type_activated(&contract_call.call_type) {
+                return Err(Flaw::CallTypeNotActivated);
+            }
+        }
+
+        Ok(message)
+    }
+
+    fn is_call_type_activated(&self, call_type: &CallType) -> bool {
+        match call_type {
+            CallType::MintNft(_) => self.rule_set.nft_tokens,
+            CallType::AddCollateral(_) | CallType::Repay(_) | CallType::WithdrawCollateral(_) => {
+                self.rule_set.collateral_account_updates
+            }
+            CallType::Liquidate(_) => self.rule_set.liquidation,
+            CallType::Batch(contract_calls) => contract_calls
+                .iter()
+                .all(|contract_call| self.is_call_type_activated(&contract_call.call_type)),
+            _ => true,
+        }
+    }
+
+    /// Run a call against the unallocated inputs, the calls without a contract call the contract
+    /// created by the message.
+    async fn process_contract_call(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_call: ContractCall,
+        message_result: &Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let (message, contract_id) = match contract_call.contract {
+            Some(contract_id) => (self.get_message(&contract_id).await, contract_id),
+            None => (message_result.clone(), block_tx.to_tuple()),
+        };
+
+        match contract_call.call_type {
+            CallType::Mint(mint_option) => {
+                self.mint(tx, block_tx, &contract_id, &mint_option, message)
+                    .await
+            }
+            CallType::Burn(burn_option) => {
+                self.burn(tx, block_tx, &contract_id, &burn_option, message)
+                    .await
+            }
+            CallType::Swap(swap_option) => {
+                self.process_swap(tx, block_tx, &contract_id, &swap_option, message)
+                    .await
+            }
+            CallType::OpenAccount(open_account_option) => {
+                self.process_open_account(tx, block_tx, &contract_id, &open_account_option, message)
+                    .await
+            }
+            CallType::CloseAccount(close_account_option) => {
+                self.process_close_account(tx, block_tx, &contract_id, &close_account_option)
+                    .await
+            }
+            // the update was a no-op before its rule set
+            CallType::UpdateNft(_) if !self.rule_set.update_nft => None,
+            CallType::UpdateNft(update_nft_option) => {
+                self.update_nft(tx, block_tx, &contract_id, &update_nft_option, message)
+                    .await
+            }
+            CallType::MintNft(mint_nft_option) => {
+                self.mint_nft_token(tx, block_tx, &contract_id, &mint_nft_option, message)
+                    .await
+            }
+            CallType::AddCollateral(add_collateral_option) => {
+                self.process_add_collateral(
+                    tx,
+                    block_tx,
+                    &contract_id,
+                    &add_collateral_option,
+                    message,
+                )
+                .await
+            }
+            CallType::Repay(repay_option) => {
+                self.process_repay(tx, block_tx, &contract_id, &repay_option, message)
+                    .await
+            }
+            CallType::WithdrawCollateral(withdraw_collateral_option) => {
+                self.process_withdraw_collateral(
+                    tx,
+                    block_tx,
+                    &contract_id,
+                    &withdraw_collateral_option,
+                    message,
+                )
+                .await
+            }
+            CallType::Liquidate(liquidate_option) => {
+                self.process_liquidate(tx, block_tx, &contract_id, &liquidate_option, message)
+                    .await
+            }
+            // batches are not nested, see the message validation
+            CallType::Batch(_) => Some(Flaw::InvalidBatchCall),
+        }
+    }
+
     pub async fn transfers(
         &mut self,
         tx: &Transaction,
@@ -660,6 +946,7 @@ impl Updater {
     ) -> Result<Option<ContractInfo>, Flaw> {
         let message = self.get_message(&block_tx).await?;
         let asset_data = self.get_asset_contract_data(&block_tx).await?;
+        let total_supply = U128(asset_data.minted_supply.saturating_sub(asset_data.burned_supply));
 
         match message.contract_creation {
             Some(contract_creation) => match contract_creation.contract_type {
@@ -668,7 +955,7 @@ impl Updater {
                         ticker: moa.ticker,
                         supply_cap: moa.supply_cap,
                         divisibility: Some(moa.divisibility),
-                        total_supply: U128(asset_data.minted_supply - asset_data.burned_supply),
+                        total_supply,
                         r#type: Some(MintType {
                             preallocated: if moa.mint_mechanism.preallocated.is_some() {
                                 Some(true)
@@ -694,7 +981,7 @@ impl Updater {
                     ticker: mba.ticker,
                     supply_cap: mba.supply_cap,
                     divisibility: Some(mba.divisibility),
-                    total_supply: U128(asset_data.minted_supply - asset_data.burned_supply),
+                    total_supply,
                     r#type: Some(MintType {
                         preallocated: if mba.mint_mechanism.preallocated.is_some() {
                             Some(true)
@@ -741,6 +1028,7 @@ impl Updater {
                             } else {
                                 Some(CollateralizedSimple {
                                     assets: simple_assets,
+                                    swap_fee: mba.swap_mechanism.fee,
                                 })
                             }
                         } else {
@@ -754,7 +1042,7 @@ impl Updater {
                     ticker: None,
                     supply_cap: None,
                     divisibility: None,
-                    total_supply: U128(asset_data.minted_supply - asset_data.burned_supply),
+                    total_supply,
                     r#type: None,
                     asset: Some(nft.asset),
                 })),
@@ -788,6 +1076,7 @@ impl Updater {
         let outcome = MessageDataOutcome {
             message: Some(message.clone()),
             flaw: None,
+            call_flaws: None,
         };
 
         if !self.is_read_only {
@@ -835,6 +1124,21 @@ impl Updater {
         }
     }
 
+    async fn set_collateralized_contract_data(
+        &self,
+        contract_id: &BlockTxTuple,
+        collateralized_contract_data: &CollateralizedAssetData,
+    ) {
+        if !self.is_read_only {
+            let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+            self.database.lock().await.put(
+                COLLATERALIZED_CONTRACT_DATA,
+                &contract_key,
+                collateralized_contract_data,
+            );
+        }
+    }
+
     async fn set_asset_contract_data(
         &self,
         contract_id: &BlockTxTuple,
@@ -883,6 +1187,41 @@ impl Updater {
         }
     }
 
+    /// Mint that used the commitment message on the contract, if any.
+    pub async fn get_commitment_usage(
+        &self,
+        contract_id: &BlockTxTuple,
+        commitment_message: &CommitmentMessage,
+    ) -> Result<Option<BlockTxTuple>, Flaw> {
+        let commitment_key = commitment_key(contract_id, commitment_message);
+        let data: Result<BlockTxTuple, DatabaseError> = self
+            .database
+            .lock()
+            .await
+            .get(COMMITMENT_PREFIX, &commitment_key);
+
+        match data {
+            Ok(data) => Ok(Some(data)),
+            Err(DatabaseError::NotFound) => Ok(None),
+            Err(DatabaseError::DeserializeFailed) => Err(Flaw::FailedDeserialization),
+        }
+    }
+
+    async fn set_commitment_usage(
+        &self,
+        contract_id: &BlockTxTuple,
+        commitment_message: &CommitmentMessage,
+        block_tx: &BlockTx,
+    ) {
+        if !self.is_read_only {
+            let commitment_key = commitment_key(contract_id, commitment_message);
+            self.database
+                .lock()
+                .await
+                .put(COMMITMENT_PREFIX, &commitment_key, block_tx.to_tuple());
+        }
+    }
+
     // TODO: separate the updater helper-api into dedicated file.
     #[cfg(feature = "helper-api")]
     pub async fn get_address_balance(&self, address: String) -> Result<AddressAssetList, Flaw> {
@@ -1,4 +1,5 @@
-use bitcoin::OutPoint;
+use bitcoin::{OutPoint, PublicKey};
+use std::cmp::min;
 use collateralized::CollateralizedAssetData;
 use database::COLLATERALIZED_CONTRACT_DATA;
 use message::MintBurnOption;
@@ -28,6 +29,20 @@ impl Updater {
         if burned_amount == 0 {
             return Some(Flaw::InsufficientInputAmount);
         }
+        let mut burned_supply = burned_amount;
+
+        // fees are kept by the contract unless there is a fee key, its output is required
+        let mut fee_values: Vec<u128> = vec![];
+        let mut fee_output = None;
+        if let (Some(_), Some(fee_pay_to_key)) =
+            (&return_collateral.fee, &return_collateral.fee_pay_to_key)
+        {
+            let pubkey = PublicKey::from_slice(fee_pay_to_key).unwrap();
+            fee_output = output_paying_to_key(tx, &pubkey);
+            if fee_output.is_none() {
+                return Some(Flaw::FeeOutputNotFound);
+            }
+        }
 
         if let Some(collateralized) = &mba.mint_mechanism.collateralized {
             match &collateralized.mint_structure {
@@ -42,7 +57,20 @@ impl Updater {
                     );
 
                     if let Ok(_out_value) = process_ratio_result {
-                        out_values.push(_out_value);
+                        let fee = return_collateral.fee_amount(_out_value);
+                        let out_value = _out_value - fee;
+
+                        if let Some(flaw) = self.validate_assert_values(
+                            &burn_option.assert_values,
+                            vec![burned_amount],
+                            None,
+                            out_value,
+                        ) {
+                            return Some(flaw);
+                        }
+
+                        out_values.push(out_value);
+                        fee_values.push(fee);
                     } else {
                         return process_ratio_result.err();
                     }
@@ -109,13 +137,35 @@ impl Updater {
                                 return Some(Flaw::InsufficientOutputAmount);
                             }
 
+                            // The fee stays in the pool unless it is paid to the fee key
+                            let fee0 = return_collateral.fee_amount(return_amount0);
+                            let fee1 = return_collateral.fee_amount(return_amount1);
+                            let (withdrawn_amount0, withdrawn_amount1) = if fee_output.is_some() {
+                                (return_amount0, return_amount1)
+                            } else {
+                                (return_amount0 - fee0, return_amount1 - fee1)
+                            };
+                            let new_pool_amounts0 =
+                                existing_pool_amounts0.saturating_sub(withdrawn_amount0);
+                            let new_pool_amounts1 =
+                                existing_pool_amounts1.saturating_sub(withdrawn_amount1);
+
+                            if let Some(flaw) = self.validate_assert_values(
+                                &burn_option.assert_values,
+                                vec![burned_amount],
+                                Some(vec![new_pool_amounts0, new_pool_amounts1]),
+                                min(return_amount0 - fee0, return_amount1 - fee1),
+                            ) {
+                                return Some(flaw);
+                            }
+
                             // Update pool state
                             pool_data
                                 .amounts
-                                .insert(first_asset_id.to_string(), existing_pool_amounts0.saturating_sub(return_amount0));
+                                .insert(first_asset_id.to_string(), new_pool_amounts0);
                             pool_data
                                 .amounts
-                                .insert(second_asset_id.to_string(), existing_pool_amounts1.saturating_sub(return_amount1));
+                                .insert(second_asset_id.to_string(), new_pool_amounts1);
                             pool_data.total_supply =
                                 pool_data.total_supply.saturating_sub(burned_amount);
 
@@ -126,8 +176,10 @@ impl Updater {
                                     .put(COLLATERALIZED_CONTRACT_DATA, &pool_key, pool_data);
                             }
 
-                            out_values.push(return_amount0);
-                            out_values.push(return_amount1);
+                            out_values.push(return_amount0 - fee0);
+                            out_values.push(return_amount1 - fee1);
+                            fee_values.push(fee0);
+                            fee_values.push(fee1);
                         },
                         RatioModel::ConstantSum => {
                             // Get pool data
@@ -182,15 +234,35 @@ impl Updater {
                                 return Some(Flaw::InsufficientOutputAmount);
                             }
 
+                            // The fee stays in the pool unless it is paid to the fee key
+                            let fee0 = return_collateral.fee_amount(return_amount0);
+                            let fee1 = return_collateral.fee_amount(return_amount1);
+                            let (withdrawn_amount0, withdrawn_amount1) = if fee_output.is_some() {
+                                (return_amount0, return_amount1)
+                            } else {
+                                (return_amount0 - fee0, return_amount1 - fee1)
+                            };
+                            let new_pool_amounts0 =
+                                existing_pool_amounts0.saturating_sub(withdrawn_amount0);
+                            let new_pool_amounts1 =
+                                existing_pool_amounts1.saturating_sub(withdrawn_amount1);
+
+                            if let Some(flaw) = self.validate_assert_values(
+                                &burn_option.assert_values,
+                                vec![burned_amount],
+                                Some(vec![new_pool_amounts0, new_pool_amounts1]),
+                                min(return_amount0 - fee0, return_amount1 - fee1),
+                            ) {
+                                return Some(flaw);
+                            }
+
                             // Update pool state by subtracting the withdrawn amounts.
-                            pool_data.amounts.insert(
-                                first_asset_id.to_string(),
-                                existing_pool_amounts0.saturating_sub(return_amount0),
-                            );
-                            pool_data.amounts.insert(
-                                second_asset_id.to_string(),
-                                existing_pool_amounts1.saturating_sub(return_amount1),
-                            );
+                            pool_data
+                                .amounts
+                                .insert(first_asset_id.to_string(), new_pool_amounts0);
+                            pool_data
+                                .amounts
+                                .insert(second_asset_id.to_string(), new_pool_amounts1);
                             pool_data.total_supply = pool_data.total_supply.saturating_sub(burned_amount);
 
                             if !self.is_read_only {
@@ -200,8 +272,10 @@ impl Updater {
                                     .put(COLLATERALIZED_CONTRACT_DATA, &pool_key, pool_data);
                             }
 
-                            out_values.push(return_amount0);
-                            out_values.push(return_amount1);
+                            out_values.push(return_amount0 - fee0);
+                            out_values.push(return_amount1 - fee1);
+                            fee_values.push(fee0);
+                            fee_values.push(fee1);
                         },
                     }
                 }
@@ -220,14 +294,14 @@ impl Updater {
                     let collateral_account_outpoint: Option<OutPoint> = self
                         .unallocated_inputs
                         .helper_outpoint_collateral_accounts
-                        .remove(&collateral_account);
+                        .remove(&BlockTx::from_tuple(*contract_id).to_string());
 
                     if let Some(oracle_message_signed) = &burn_option.oracle_message {
                         if let Some(expected_input_outpoint) =
                             oracle_message_signed.message.input_outpoint
                         {
                             if let Some(oracle_setting) = &return_collateral.oracle_setting {
-                                if expected_input_outpoint != collateral_account_outpoint.unwrap() {
+                                if Some(expected_input_outpoint) != collateral_account_outpoint {
                                     return Some(Flaw::OracleMintFailed);
                                 }
 
@@ -259,11 +333,36 @@ impl Updater {
 
                             // verify burned asset
                             if let Some(out_value) = &oracle_message_signed.message.out_value {
-                                if burned_amount < out_value.0 {
+                                // the fee is burned on top of the out value
+                                let fee = return_collateral.fee_amount(out_value.0);
+                                if burned_amount < out_value.0.saturating_add(fee) {
                                     return Some(Flaw::BurnValueIncorrect);
                                 }
 
-                                let burned_remainder = burned_amount - out_value.0;
+                                let burned_remainder = burned_amount - out_value.0 - fee;
+
+                                // the remainder and the fee paid to the fee key aren't burned
+                                burned_supply = if fee_output.is_some() {
+                                    out_value.0
+                                } else {
+                                    out_value.0 + fee
+                                };
+
+                                if let Some(flaw) = self.validate_assert_values(
+                                    &burn_option.assert_values,
+                                    vec![burned_amount],
+                                    None,
+                                    burned_remainder,
+                                ) {
+                                    return Some(flaw);
+                                }
+
+                                if let Some(fee_output) = fee_output {
+                                    if fee > 0 {
+                                        self.allocate_new_asset(fee_output, contract_id, fee)
+                                            .await;
+                                    }
+                                }
 
                                 if burned_remainder > 0 {
                                     if let Some(pointer) = burn_option.pointer {
@@ -313,7 +412,7 @@ impl Updater {
                 .validate_and_update_supply_cap(
                     contract_id,
                     None,
-                    burned_amount,
+                    burned_supply,
                     false,
                     false,
                     None,
@@ -336,6 +435,17 @@ impl Updater {
                     }
                 }
             }
+
+            if let Some(fee_output) = fee_output {
+                for (pos, fee) in fee_values.iter().enumerate() {
+                    if *fee == 0 {
+                        continue;
+                    }
+                    if let InputAsset::GlittrAsset(asset_id) = collateralized.input_assets[pos] {
+                        self.allocate_new_asset(fee_output, &asset_id, *fee).await;
+                    }
+                }
+            }
         } else {
             return Some(Flaw::InvalidContractType);
         }
@@ -362,10 +472,20 @@ impl Updater {
                         }
 
                         if let Some(return_collateral) = &mba.burn_mechanism.return_collateral {
+                            // the fees are charged from the rule set that added them
+                            let return_collateral = if self.rule_set.charge_return_collateral_fees {
+                                return_collateral.clone()
+                            } else {
+                                ReturnCollateral {
+                                    fee: None,
+                                    fee_pay_to_key: None,
+                                    ..return_collateral.clone()
+                                }
+                            };
                             return self
                                 .burn_return_collateral(
                                     &mba,
-                                    return_collateral,
+                                    &return_collateral,
                                     tx,
                                     block_tx,
                                     contract_id,
@@ -1,23 +1,271 @@
 use num::integer::Roots;
-use std::cmp::min;
+use std::cmp::{max, min};
 
 use super::*;
 use crate::updater::database::COLLATERAL_ACCOUNTS_PREFIX;
 use bitcoin::{OutPoint, Transaction};
-use database::COLLATERALIZED_CONTRACT_DATA;
-use message::{CloseAccountOption, MintBurnOption, OpenAccountOption, SwapOption};
-use mint_burn_asset::{Collateralized, MintBurnAssetContract, MintStructure, RatioModel};
-
-#[derive(Serialize, Deserialize, Default)]
+use database::{COLLATERALIZED_CONTRACT_DATA, CONTRACT_COLLATERAL_ACCOUNTS_PREFIX};
+use message::{
+    AddCollateralOption, CloseAccountOption, LiquidateOption, MintBurnOption, OpenAccountOption,
+    OracleMessageSigned, RepayOption, SwapOption, WithdrawCollateralOption,
+};
+use mint_burn_asset::{
+    AccountType, Collateralized, MintBurnAssetContract, MintStructure, RatioModel,
+};
+use transaction_shared::RatioType;
+
+/// Reserves of a proportional pool with `total_supply` as the issued LP supply, or the
+/// collateral of the accounts of an account contract with `shares` as the shares issued to the
+/// accounts per collateral asset.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Default, Clone, Debug)]
 pub struct CollateralizedAssetData {
     pub amounts: HashMap<BlockTxString, u128>,
     pub total_supply: u128,
+    pub shares: HashMap<BlockTxString, u128>,
+}
+
+impl CollateralizedAssetData {
+    /// Shares of the asset issued for `amount`, one share per unit until shares are issued.
+    fn shares_for_amount(&self, asset_id: &str, amount: u128) -> u128 {
+        let collateral_amount = self.amounts.get(asset_id).cloned().unwrap_or(0);
+        let total_shares = self.shares.get(asset_id).cloned().unwrap_or(0);
+        if total_shares == 0 || collateral_amount == 0 {
+            return amount;
+        }
+
+        amount.saturating_mul(total_shares) / collateral_amount
+    }
+
+    /// Amount of the asset paid for `shares` of it.
+    fn amount_for_shares(&self, asset_id: &str, shares: u128) -> u128 {
+        let collateral_amount = self.amounts.get(asset_id).cloned().unwrap_or(0);
+        match self.shares.get(asset_id) {
+            Some(total_shares) if *total_shares > 0 => {
+                shares.saturating_mul(collateral_amount) / total_shares
+            }
+            _ => 0,
+        }
+    }
+
+    /// Collateral held by the shares of an account per asset.
+    pub fn collateral_amounts(&self, share_amounts: &ShareAmounts) -> Vec<(BlockTxString, u128)> {
+        share_amounts
+            .iter()
+            .map(|(asset_id, shares)| (asset_id.clone(), self.amount_for_shares(asset_id, *shares)))
+            .collect()
+    }
+
+    /// Value of the collateral of the shares, the ratio of the contract prices every input
+    /// asset alike.
+    pub fn share_value(&self, share_amounts: &ShareAmounts) -> u128 {
+        self.collateral_amounts(share_amounts)
+            .iter()
+            .fold(0, |total, (_, amount)| total.saturating_add(*amount))
+    }
+
+    /// Adds the collateral and returns the shares issued for each asset.
+    fn deposit(&mut self, amounts: &[(BlockTxTuple, u128)]) -> ShareAmounts {
+        let mut share_amounts = ShareAmounts::new();
+        for (asset_id, amount) in amounts {
+            let asset_id = BlockTx::from_tuple(*asset_id).to_string();
+            let shares = self.shares_for_amount(&asset_id, *amount);
+
+            let collateral_amount = self.amounts.entry(asset_id.clone()).or_default();
+            *collateral_amount = collateral_amount.saturating_add(*amount);
+            let total_shares = self.shares.entry(asset_id.clone()).or_default();
+            *total_shares = total_shares.saturating_add(shares);
+
+            let share_amount = share_amounts.entry(asset_id).or_default();
+            *share_amount = share_amount.saturating_add(shares);
+        }
+
+        share_amounts
+    }
+
+    /// Takes out the collateral from the shares of the account, each asset burns its own
+    /// shares, rounded up.
+    fn withdraw_amounts(
+        &mut self,
+        share_amounts: &mut ShareAmounts,
+        amounts: &[(BlockTxTuple, U128)],
+    ) -> Result<(), Flaw> {
+        for (asset_id, amount) in amounts {
+            if amount.0 == 0 {
+                continue;
+            }
+
+            let asset_id = BlockTx::from_tuple(*asset_id).to_string();
+            let collateral_amount = self.amounts.get(&asset_id).cloned().unwrap_or(0);
+            let total_shares = self.shares.get(&asset_id).cloned().unwrap_or(0);
+            // collateral left without shares can't be withdrawn
+            if collateral_amount < amount.0 || total_shares == 0 {
+                return Err(Flaw::CollateralAmountExceeded);
+            }
+
+            let shares = amount
+                .0
+                .saturating_mul(total_shares)
+                .div_ceil(collateral_amount);
+            let Some(share_amount) = share_amounts
+                .get_mut(&asset_id)
+                .filter(|share_amount| **share_amount >= shares)
+            else {
+                return Err(Flaw::CollateralAmountExceeded);
+            };
+
+            *share_amount -= shares;
+            self.amounts
+                .insert(asset_id.clone(), collateral_amount - amount.0);
+            self.shares.insert(asset_id, total_shares - shares);
+        }
+        share_amounts.retain(|_, share_amount| *share_amount > 0);
+
+        Ok(())
+    }
+
+    /// Takes out the collateral of the shares of each asset.
+    fn withdraw_shares(&mut self, share_amounts: &ShareAmounts) -> Vec<(BlockTxTuple, u128)> {
+        let mut withdrawn = Vec::new();
+        for (asset_id, shares) in share_amounts {
+            let amount = self.amount_for_shares(asset_id, *shares);
+            if let Some(total_shares) = self.shares.get_mut(asset_id) {
+                *total_shares = total_shares.saturating_sub(*shares);
+            }
+            let Ok(block_tx) = BlockTx::from_str(asset_id) else {
+                continue;
+            };
+            if amount == 0 {
+                continue;
+            }
+
+            if let Some(collateral_amount) = self.amounts.get_mut(asset_id) {
+                *collateral_amount -= amount;
+            }
+            withdrawn.push((block_tx.to_tuple(), amount));
+        }
+
+        withdrawn
+    }
+}
+
+/// Outpoints holding the collateral accounts of a contract.
+#[derive(Serialize, Deserialize, BorshSerialize, BorshDeserialize, Default, Debug)]
+pub struct ContractCollateralAccounts {
+    pub outpoints: HashSet<String>,
+}
+
+/// Collateral used by the outstanding amount over the collateral value of the account, the
+/// outstanding amount is borrowed at `ratio` of the collateral used.
+fn fixed_ratio_ltv(
+    collateral_account: &CollateralAccount,
+    collateral_value: u128,
+    ratio: &Fraction,
+) -> (u128, u128) {
+    let collateral_used = if ratio.0 == 0 {
+        0
+    } else {
+        collateral_account
+            .amount_outstanding
+            .saturating_mul(ratio.1 as u128)
+            .div_ceil(ratio.0 as u128)
+    };
+
+    (collateral_used, collateral_value)
+}
+
+fn exceeds_max_ltv(ltv: (u128, u128), max_ltv: &Fraction) -> bool {
+    ltv.0.saturating_mul(max_ltv.1 as u128) > ltv.1.saturating_mul(max_ltv.0 as u128)
+}
+
+/// Both sides are shifted down until they fit in a fraction.
+fn ltv_fraction(ltv: (u128, u128)) -> Fraction {
+    if ltv.0 == 0 {
+        return (0, 1);
+    }
+
+    let shift = (128 - max(ltv.0, ltv.1).leading_zeros()).saturating_sub(64);
+    ((ltv.0 >> shift) as u64, (ltv.1 >> shift) as u64)
 }
 
 impl Updater {
     impl_ops_for_outpoint_data!(CollateralAccounts);
     impl_ops_for_outpoint_data!(StateKeys);
 
+    pub async fn get_contract_collateral_accounts(
+        &self,
+        contract_id: &BlockTxString,
+    ) -> Result<ContractCollateralAccounts, Flaw> {
+        let data: Result<ContractCollateralAccounts, DatabaseError> = self
+            .database
+            .lock()
+            .await
+            .get(CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, contract_id);
+
+        match data {
+            Ok(data) => Ok(data),
+            Err(DatabaseError::NotFound) => Ok(ContractCollateralAccounts::default()),
+            Err(DatabaseError::DeserializeFailed) => Err(Flaw::FailedDeserialization),
+        }
+    }
+
+    /// Collateral of the accounts of the contract, the shares are the collateral amounts before
+    /// the collateral shares.
+    async fn get_account_collateral(
+        &self,
+        contract_id: &BlockTxTuple,
+    ) -> Result<CollateralizedAssetData, Flaw> {
+        let mut pool = self.get_collateralized_contract_data(contract_id).await?;
+        if !self.rule_set.collateral_shares {
+            pool.shares = pool.amounts.clone();
+        }
+
+        Ok(pool)
+    }
+
+    /// Accounts are indexed by contract so that they can be found without the owner's outpoint.
+    pub async fn index_collateral_accounts(
+        &self,
+        outpoint: &OutPoint,
+        collateral_accounts: &CollateralAccounts,
+        is_spent: bool,
+    ) {
+        if self.is_read_only {
+            return;
+        }
+
+        for contract_id in collateral_accounts.collateral_accounts.keys() {
+            let mut contract_collateral_accounts =
+                match self.get_contract_collateral_accounts(contract_id).await {
+                    Ok(data) => data,
+                    Err(flaw) => {
+                        log::error!("Failed to index the collateral account: {:?}", flaw);
+                        continue;
+                    }
+                };
+
+            if is_spent {
+                contract_collateral_accounts
+                    .outpoints
+                    .remove(&outpoint.to_string());
+            } else {
+                contract_collateral_accounts
+                    .outpoints
+                    .insert(outpoint.to_string());
+            }
+
+            let mut database = self.database.lock().await;
+            if contract_collateral_accounts.outpoints.is_empty() {
+                database.delete(CONTRACT_COLLATERAL_ACCOUNTS_PREFIX, contract_id);
+            } else {
+                database.put(
+                    CONTRACT_COLLATERAL_ACCOUNTS_PREFIX,
+                    contract_id,
+                    contract_collateral_accounts,
+                );
+            }
+        }
+    }
+
     pub async fn allocate_new_collateral_accounts(
         &mut self,
         vout: u32,
@@ -125,22 +373,26 @@ impl Updater {
                 let collateral_account_outpoint: Option<OutPoint> = self
                     .unallocated_inputs
                     .helper_outpoint_collateral_accounts
-                    .remove(&collateral_account);
+                    .remove(&BlockTx::from_tuple(*contract_id).to_string());
 
                 match account_type.ratio {
                     transaction_shared::RatioType::Fixed { ratio } => {
+                        let collateral_value = match self.get_account_collateral(contract_id).await
+                        {
+                            Ok(pool) => pool.share_value(&collateral_account.share_amounts),
+                            Err(flaw) => return Some(flaw),
+                        };
+
                         // Get collateral account available amount
-                        let available_amount = collateral_account.total_collateral_amount
-                            - (collateral_account.total_collateral_amount
-                                .saturating_mul(collateral_account.ltv.0 as u128))
-                            .saturating_div(collateral_account.ltv.1 as u128);
+                        let available_amount = collateral_value
+                            - (collateral_value.saturating_mul(collateral_account.ltv.0 as u128))
+                                .saturating_div(collateral_account.ltv.1 as u128);
 
                         input_values.push(available_amount);
 
                         // Allowed amount = min(total_amount * max_ltv, available_amount)
                         let allowed_amount = min(
-                            collateral_account
-                                .total_collateral_amount
+                            collateral_value
                                 .saturating_mul(account_type.max_ltv.0 as u128)
                                 .saturating_div(account_type.max_ltv.1 as u128),
                             available_amount,
@@ -158,7 +410,7 @@ impl Updater {
                             if let Some(expected_input_outpoint) =
                                 oracle_message_signed.message.input_outpoint
                             {
-                                if expected_input_outpoint != collateral_account_outpoint.unwrap() {
+                                if Some(expected_input_outpoint) != collateral_account_outpoint {
                                     return Some(Flaw::OracleMintFailed);
                                 }
 
@@ -340,6 +592,7 @@ impl Updater {
                                 let new_pool = CollateralizedAssetData {
                                     amounts,
                                     total_supply: initial_supply,
+                                    shares: HashMap::new(),
                                 };
 
                                 total_collateralized.push(input_first_asset);
@@ -466,6 +719,7 @@ impl Updater {
                                 let new_pool = CollateralizedAssetData {
                                     amounts,
                                     total_supply: initial_supply,
+                                    shares: HashMap::new(),
                                 };
                                 total_collateralized.push(input_first_asset);
                                 total_collateralized.push(input_second_asset);
@@ -557,11 +811,18 @@ impl Updater {
             return Some(flaw);
         }
 
-        // Return collateral assets to user
-        for (asset_id, amount) in collateral_account.collateral_amounts {
+        let mut pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+
+        // Return the collateral of the shares to user
+        for (asset_id, amount) in pool.withdraw_shares(&collateral_account.share_amounts) {
             self.allocate_new_asset(close_account_option.pointer, &asset_id, amount)
                 .await;
         }
+        self.set_collateralized_contract_data(contract_id, &pool)
+            .await;
 
         // Delete collateral account
         self.unallocated_inputs
@@ -572,6 +833,525 @@ impl Updater {
         None
     }
 
+    fn collateral_account_contract(
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Result<(Collateralized, AccountType), Flaw> {
+        let contract_creation = message?.contract_creation.ok_or(Flaw::ContractNotMatch)?;
+
+        let ContractType::Mba(mba) = contract_creation.contract_type else {
+            return Err(Flaw::InvalidContractType);
+        };
+        let Some(collateralized) = mba.mint_mechanism.collateralized else {
+            return Err(Flaw::InvalidContractType);
+        };
+        let MintStructure::Account(account_type) = collateralized.mint_structure.clone() else {
+            return Err(Flaw::InvalidContractType);
+        };
+
+        Ok((collateralized, account_type))
+    }
+
+    /// The collateral account of the contract spent by the transaction, with its outpoint.
+    fn get_input_collateral_account(
+        &self,
+        contract_id: &BlockTxTuple,
+    ) -> Result<(CollateralAccount, Option<OutPoint>), Flaw> {
+        let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+        let collateral_account = self
+            .unallocated_inputs
+            .collateral_accounts
+            .collateral_accounts
+            .get(&contract_key)
+            .cloned()
+            .ok_or(Flaw::CollateralAccountNotFound)?;
+
+        let collateral_account_outpoint = self
+            .unallocated_inputs
+            .helper_outpoint_collateral_accounts
+            .get(&contract_key)
+            .cloned();
+
+        Ok((collateral_account, collateral_account_outpoint))
+    }
+
+    /// Oracle accounts take the ltv and outstanding amount signed for the account outpoint,
+    /// fixed ratio accounts are repriced from their outstanding amount and share value.
+    fn update_collateral_account_ltv(
+        &self,
+        account_type: &AccountType,
+        collateral_account: &mut CollateralAccount,
+        pool: &CollateralizedAssetData,
+        collateral_account_outpoint: Option<OutPoint>,
+        oracle_message: &Option<OracleMessageSigned>,
+        block_tx: &BlockTx,
+    ) -> Option<Flaw> {
+        match &account_type.ratio {
+            RatioType::Fixed { ratio } => {
+                let collateral_value = pool.share_value(&collateral_account.share_amounts);
+                let ltv = fixed_ratio_ltv(collateral_account, collateral_value, ratio);
+                if exceeds_max_ltv(ltv, &account_type.max_ltv) {
+                    return Some(Flaw::MaxLtvExceeded);
+                }
+
+                collateral_account.ltv = ltv_fraction(ltv);
+            }
+            RatioType::Oracle { setting } => {
+                let Some(oracle_message_signed) = oracle_message else {
+                    return Some(Flaw::OracleMessageNotFound);
+                };
+
+                let input_outpoint = oracle_message_signed.message.input_outpoint;
+                if input_outpoint.is_none() || input_outpoint != collateral_account_outpoint {
+                    return Some(Flaw::OracleMintFailed);
+                }
+
+                if let Some(flaw) =
+                    self.validate_oracle_message(oracle_message_signed, setting, block_tx)
+                {
+                    return Some(flaw);
+                }
+
+                let Some(ltv) = oracle_message_signed.message.ltv else {
+                    return Some(Flaw::LtvMustBeUpdated);
+                };
+                if ltv.1 == 0 {
+                    return Some(Flaw::FractionInvalid);
+                }
+                if exceeds_max_ltv((ltv.0 as u128, ltv.1 as u128), &account_type.max_ltv) {
+                    return Some(Flaw::MaxLtvExceeded);
+                }
+
+                let Some(outstanding) = &oracle_message_signed.message.outstanding else {
+                    return Some(Flaw::OutstandingMustBeUpdated);
+                };
+
+                collateral_account.ltv = ltv;
+                collateral_account.amount_outstanding = outstanding.0;
+            }
+        }
+
+        None
+    }
+
+    /// The updated collateral account replaces the spent one.
+    async fn reallocate_collateral_account(
+        &mut self,
+        pointer_to_key: u32,
+        contract_id: &BlockTxTuple,
+        collateral_account: &CollateralAccount,
+    ) {
+        let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+        self.unallocated_inputs
+            .collateral_accounts
+            .collateral_accounts
+            .remove(&contract_key);
+
+        self.allocate_new_collateral_accounts(pointer_to_key, collateral_account, contract_key)
+            .await;
+    }
+
+    pub async fn process_add_collateral(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        add_collateral_option: &AddCollateralOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let (collateralized, account_type) = match Updater::collateral_account_contract(message) {
+            Ok(contract) => contract,
+            Err(flaw) => return Some(flaw),
+        };
+
+        let (mut collateral_account, collateral_account_outpoint) =
+            match self.get_input_collateral_account(contract_id) {
+                Ok(collateral_account) => collateral_account,
+                Err(flaw) => return Some(flaw),
+            };
+
+        let mut added_amounts = Vec::new();
+        for input_asset in &collateralized.input_assets {
+            if let InputAsset::GlittrAsset(asset_id) = input_asset {
+                let amount = self
+                    .unallocated_inputs
+                    .asset_list
+                    .list
+                    .get(&BlockTx::from_tuple(*asset_id).to_string())
+                    .cloned()
+                    .unwrap_or(0);
+
+                if amount > 0 {
+                    added_amounts.push((*asset_id, amount));
+                }
+            }
+        }
+
+        if added_amounts.is_empty() {
+            return Some(Flaw::InsufficientInputAmount);
+        }
+
+        let mut pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+        for (asset_id, shares) in pool.deposit(&added_amounts) {
+            let share_amount = collateral_account.share_amounts.entry(asset_id).or_default();
+            *share_amount = share_amount.saturating_add(shares);
+        }
+
+        if let Some(flaw) = self.update_collateral_account_ltv(
+            &account_type,
+            &mut collateral_account,
+            &pool,
+            collateral_account_outpoint,
+            &add_collateral_option.oracle_message,
+            block_tx,
+        ) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.validate_pointer(add_collateral_option.pointer_to_key, tx) {
+            return Some(flaw);
+        }
+
+        for (asset_id, _) in &added_amounts {
+            self.unallocated_inputs
+                .asset_list
+                .list
+                .remove(&BlockTx::from_tuple(*asset_id).to_string());
+        }
+
+        self.set_collateralized_contract_data(contract_id, &pool)
+            .await;
+        self.reallocate_collateral_account(
+            add_collateral_option.pointer_to_key,
+            contract_id,
+            &collateral_account,
+        )
+        .await;
+
+        None
+    }
+
+    pub async fn process_repay(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        repay_option: &RepayOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let (_, account_type) = match Updater::collateral_account_contract(message) {
+            Ok(contract) => contract,
+            Err(flaw) => return Some(flaw),
+        };
+
+        let (mut collateral_account, collateral_account_outpoint) =
+            match self.get_input_collateral_account(contract_id) {
+                Ok(collateral_account) => collateral_account,
+                Err(flaw) => return Some(flaw),
+            };
+
+        let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+        let burned_amount = self
+            .unallocated_inputs
+            .asset_list
+            .list
+            .get(&contract_key)
+            .cloned()
+            .unwrap_or(0);
+
+        if burned_amount == 0 {
+            return Some(Flaw::InsufficientInputAmount);
+        }
+
+        // oracle accounts repay the out value of the oracle message, it may include interest
+        let repaid_amount = match &account_type.ratio {
+            RatioType::Fixed { .. } => min(burned_amount, collateral_account.amount_outstanding),
+            RatioType::Oracle { .. } => {
+                let Some(oracle_message_signed) = &repay_option.oracle_message else {
+                    return Some(Flaw::OracleMessageNotFound);
+                };
+                let Some(out_value) = &oracle_message_signed.message.out_value else {
+                    return Some(Flaw::OutValueNotFound);
+                };
+                if burned_amount < out_value.0 {
+                    return Some(Flaw::BurnValueIncorrect);
+                }
+                out_value.0
+            }
+        };
+
+        collateral_account.amount_outstanding = collateral_account
+            .amount_outstanding
+            .saturating_sub(repaid_amount);
+
+        let pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+
+        if let Some(flaw) = self.update_collateral_account_ltv(
+            &account_type,
+            &mut collateral_account,
+            &pool,
+            collateral_account_outpoint,
+            &repay_option.oracle_message,
+            block_tx,
+        ) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.validate_pointer(repay_option.pointer, tx) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.validate_pointer(repay_option.pointer_to_key, tx) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self
+            .validate_and_update_supply_cap(contract_id, None, repaid_amount, false, false, None)
+            .await
+        {
+            return Some(flaw);
+        }
+
+        // the inputs are only consumed once the call is valid
+        self.unallocated_inputs.asset_list.list.remove(&contract_key);
+
+        let remainder = burned_amount - repaid_amount;
+        if remainder > 0 {
+            self.allocate_new_asset(repay_option.pointer, contract_id, remainder)
+                .await;
+        }
+
+        self.reallocate_collateral_account(
+            repay_option.pointer_to_key,
+            contract_id,
+            &collateral_account,
+        )
+        .await;
+
+        None
+    }
+
+    pub async fn process_withdraw_collateral(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        withdraw_collateral_option: &WithdrawCollateralOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let (_, account_type) = match Updater::collateral_account_contract(message) {
+            Ok(contract) => contract,
+            Err(flaw) => return Some(flaw),
+        };
+
+        let (mut collateral_account, collateral_account_outpoint) =
+            match self.get_input_collateral_account(contract_id) {
+                Ok(collateral_account) => collateral_account,
+                Err(flaw) => return Some(flaw),
+            };
+
+        let mut pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+
+        // the shares of each withdrawn asset are burned, the account can't take another asset
+        if let Err(flaw) = pool.withdraw_amounts(
+            &mut collateral_account.share_amounts,
+            &withdraw_collateral_option.amounts,
+        ) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.update_collateral_account_ltv(
+            &account_type,
+            &mut collateral_account,
+            &pool,
+            collateral_account_outpoint,
+            &withdraw_collateral_option.oracle_message,
+            block_tx,
+        ) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.validate_pointer(withdraw_collateral_option.pointer, tx) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self.validate_pointer(withdraw_collateral_option.pointer_to_key, tx) {
+            return Some(flaw);
+        }
+
+        for (asset_id, amount) in &withdraw_collateral_option.amounts {
+            if amount.0 > 0 {
+                self.allocate_new_asset(withdraw_collateral_option.pointer, asset_id, amount.0)
+                    .await;
+            }
+        }
+
+        self.set_collateralized_contract_data(contract_id, &pool)
+            .await;
+        self.reallocate_collateral_account(
+            withdraw_collateral_option.pointer_to_key,
+            contract_id,
+            &collateral_account,
+        )
+        .await;
+
+        None
+    }
+
+    /// The liquidated account stays at the owner's outpoint, repaid and without the seized
+    /// collateral. The liquidator repays the outstanding amount signed by the oracle, the oracle
+    /// ratio prices the collateral paid per repaid asset.
+    pub async fn process_liquidate(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        liquidate_option: &LiquidateOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let (_, account_type) = match Updater::collateral_account_contract(message) {
+            Ok(contract) => contract,
+            Err(flaw) => return Some(flaw),
+        };
+
+        let (Some(liquidation), RatioType::Oracle { setting }) =
+            (&account_type.liquidation, &account_type.ratio)
+        else {
+            return Some(Flaw::LiquidationNotAllowed);
+        };
+
+        let oracle_message_signed = &liquidate_option.oracle_message;
+        if let Some(flaw) = self.validate_oracle_message(oracle_message_signed, setting, block_tx) {
+            return Some(flaw);
+        }
+        let oracle_message = &oracle_message_signed.message;
+
+        let Some(collateral_account_outpoint) = oracle_message.input_outpoint else {
+            return Some(Flaw::CollateralAccountNotFound);
+        };
+
+        let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+        let mut collateral_accounts = match self
+            .get_collateral_accounts(&collateral_account_outpoint)
+            .await
+        {
+            Ok(collateral_accounts) => collateral_accounts,
+            Err(flaw) => return Some(flaw),
+        };
+        let Some(collateral_account) = collateral_accounts
+            .collateral_accounts
+            .get_mut(&contract_key)
+        else {
+            return Some(Flaw::CollateralAccountNotFound);
+        };
+
+        // a liquidated account keeps its outpoint, the same message can't liquidate it twice
+        if collateral_account.amount_outstanding == 0 {
+            return Some(Flaw::AccountNotLiquidatable);
+        }
+
+        let Some(ltv) = oracle_message.ltv else {
+            return Some(Flaw::LtvMustBeUpdated);
+        };
+        if ltv.1 == 0 || !exceeds_max_ltv((ltv.0 as u128, ltv.1 as u128), &liquidation.threshold) {
+            return Some(Flaw::AccountNotLiquidatable);
+        }
+
+        let Some(outstanding) = &oracle_message.outstanding else {
+            return Some(Flaw::OutstandingMustBeUpdated);
+        };
+        let Some(ratio) = oracle_message.ratio else {
+            return Some(Flaw::OracleRatioNotFound);
+        };
+        if ratio.1 == 0 {
+            return Some(Flaw::DivideByZero);
+        }
+
+        let burned_amount = self
+            .unallocated_inputs
+            .asset_list
+            .list
+            .get(&contract_key)
+            .cloned()
+            .unwrap_or(0);
+        if outstanding.0 == 0 || burned_amount < outstanding.0 {
+            return Some(Flaw::BurnValueIncorrect);
+        }
+
+        let mut pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+
+        // the same part of the shares of every collateral asset is seized
+        let collateral_value = outstanding.0.saturating_mul(ratio.0 as u128) / ratio.1 as u128;
+        let account_value = pool.share_value(&collateral_account.share_amounts);
+        let seized_value = min(liquidation.seized_amount(collateral_value), account_value);
+        let seized_shares: ShareAmounts = collateral_account
+            .share_amounts
+            .iter()
+            .map(|(asset_id, shares)| {
+                let seized = if seized_value == account_value {
+                    *shares
+                } else {
+                    shares.saturating_mul(seized_value) / account_value
+                };
+                (asset_id.clone(), seized)
+            })
+            .collect();
+        let seized_amounts = pool.withdraw_shares(&seized_shares);
+
+        for (asset_id, seized) in &seized_shares {
+            if let Some(shares) = collateral_account.share_amounts.get_mut(asset_id) {
+                *shares -= seized;
+            }
+        }
+        collateral_account
+            .share_amounts
+            .retain(|_, shares| *shares > 0);
+        collateral_account.amount_outstanding = 0;
+        collateral_account.ltv = (0, 1);
+
+        if let Some(flaw) = self.validate_pointer(liquidate_option.pointer, tx) {
+            return Some(flaw);
+        }
+
+        if let Some(flaw) = self
+            .validate_and_update_supply_cap(contract_id, None, outstanding.0, false, false, None)
+            .await
+        {
+            return Some(flaw);
+        }
+
+        self.unallocated_inputs.asset_list.list.remove(&contract_key);
+
+        let remainder = burned_amount - outstanding.0;
+        if remainder > 0 {
+            self.allocate_new_asset(liquidate_option.pointer, contract_id, remainder)
+                .await;
+        }
+
+        for (asset_id, amount) in &seized_amounts {
+            self.allocate_new_asset(liquidate_option.pointer, asset_id, *amount)
+                .await;
+        }
+
+        self.set_collateralized_contract_data(contract_id, &pool)
+            .await;
+        self.set_collateral_accounts(&collateral_account_outpoint, &collateral_accounts)
+            .await;
+
+        None
+    }
+
     // call together with transfer
     pub async fn process_open_account(
         &mut self,
@@ -583,7 +1363,6 @@ impl Updater {
     ) -> Option<Flaw> {
         // Get the MBA contract
         let mut collateral_amounts = Vec::new();
-        let mut total_collateral_amount: u128 = 0;
 
         match message {
             Ok(op_return_message) => {
@@ -605,7 +1384,6 @@ impl Updater {
 
                                     if burned_amount > 0 {
                                         collateral_amounts.push(((asset_id), burned_amount));
-                                        total_collateral_amount += burned_amount;
                                     }
                                 }
                             }
@@ -619,10 +1397,22 @@ impl Updater {
             Err(_) => return Some(Flaw::ContractNotMatch),
         }
 
+        let mut pool = match self.get_account_collateral(contract_id).await {
+            Ok(pool) => pool,
+            Err(flaw) => return Some(flaw),
+        };
+
+        // every deposited asset issues at least the minimum shares
+        let share_amounts = pool.deposit(&collateral_amounts);
+        if share_amounts
+            .values()
+            .any(|shares| *shares < open_account_option.share_amount.0)
+        {
+            return Some(Flaw::InsufficientShareAmount);
+        }
+
         let collateral_account = CollateralAccount {
-            collateral_amounts,
-            total_collateral_amount,
-            share_amount: open_account_option.share_amount.0,
+            share_amounts,
             ltv: (0, 100),
             amount_outstanding: 0,
         };
@@ -631,6 +1421,9 @@ impl Updater {
             return Some(flaw);
         }
 
+        self.set_collateralized_contract_data(contract_id, &pool)
+            .await;
+
         self.allocate_new_collateral_accounts(
             open_account_option.pointer_to_key,
             &collateral_account,
@@ -734,10 +1527,19 @@ impl Updater {
                             second_asset_id.clone()
                         };
 
+                        // The fee is taken from the input amount but the whole input goes to
+                        // the pool reserves, so the fee accrues to the LP holders
+                        let fee_amount = if self.rule_set.charge_swap_fees {
+                            mba.swap_mechanism.fee_amount(input_amount)
+                        } else {
+                            0
+                        };
+                        let input_amount_after_fee = input_amount.saturating_sub(fee_amount);
+
                         match proportional_type.ratio_model {
                             RatioModel::ConstantProduct => {
                                 // Existing constant product logic:
-                                // out_amount = (reserve_out * input_amount) / (reserve_in + input_amount)
+                                // out_amount = (reserve_out * input_after_fee) / (reserve_in + input_after_fee)
                                 let existing_amount_out = pool_data
                                     .amounts
                                     .get(&out_id.to_string())
@@ -753,8 +1555,10 @@ impl Updater {
                                     return Some(Flaw::PoolNotFound);
                                 }
 
-                                let numerator = existing_amount_out.saturating_mul(input_amount);
-                                let denominator = existing_amount_in.saturating_add(input_amount);
+                                let numerator =
+                                    existing_amount_out.saturating_mul(input_amount_after_fee);
+                                let denominator =
+                                    existing_amount_in.saturating_add(input_amount_after_fee);
                                 let out_value = numerator.saturating_div(denominator);
                                 if out_value == 0 {
                                     return Some(Flaw::InsufficientOutputAmount);
@@ -812,7 +1616,7 @@ impl Updater {
                             }
                             RatioModel::ConstantSum => {
                                 // New CSMM logic:
-                                // For a constant-sum pool, the output is simply the minimum of the input amount and the available counter asset.
+                                // For a constant-sum pool, the output is simply the minimum of the input amount after fee and the available counter asset.
                                 let existing_amount_out = pool_data
                                     .amounts
                                     .get(&out_id.to_string())
@@ -828,7 +1632,7 @@ impl Updater {
                                     return Some(Flaw::PoolNotFound);
                                 }
 
-                                let out_value = min(input_amount, existing_amount_out);
+                                let out_value = min(input_amount_after_fee, existing_amount_out);
                                 if out_value == 0 {
                                     return Some(Flaw::InsufficientOutputAmount);
                                 }
@@ -885,5 +1689,64 @@ impl Updater {
 
         None
     }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collateral_shares_across_assets() {
+        let mut pool = CollateralizedAssetData::default();
+        let shares = |share_amounts: &[(&str, u128)]| -> ShareAmounts {
+            share_amounts
+                .iter()
+                .map(|(asset_id, shares)| (asset_id.to_string(), *shares))
+                .collect()
+        };
+
+        // each asset issues its own shares, one per unit until shares are issued
+        let mut first = pool.deposit(&[((1, 0), 60_000), ((2, 0), 40_000)]);
+        assert_eq!(first, shares(&[("1:0", 60_000), ("2:0", 40_000)]));
+        let mut second = pool.deposit(&[((2, 0), 50_000)]);
+        assert_eq!(second, shares(&[("2:0", 50_000)]));
+        assert_eq!(pool.shares["2:0"], 90_000);
+        assert_eq!(pool.share_value(&first), 100_000);
+
+        // withdrawn amounts burn the shares of their asset, rounded up
+        assert_eq!(pool.withdraw_amounts(&mut first, &[((1, 0), U128(1))]), Ok(()));
+        assert_eq!(first["1:0"], 59_999);
+        assert_eq!(
+            pool.withdraw_amounts(&mut second, &[((2, 0), U128(50_001))]),
+            Err(Flaw::CollateralAmountExceeded)
+        );
+
+        // the last shares of an asset take all of it
+        assert_eq!(
+            pool.withdraw_shares(&first),
+            vec![((1, 0), 59_999), ((2, 0), 40_000)]
+        );
+        assert_eq!(pool.amounts["1:0"], 0);
+        assert_eq!(pool.withdraw_shares(&second), vec![((2, 0), 50_000)]);
+        assert_eq!(pool.amounts["2:0"], 0);
+        assert_eq!(pool.shares["2:0"], 0);
+    }
 
+    #[test]
+    fn test_collateral_shares_keep_assets_apart() {
+        let mut pool = CollateralizedAssetData::default();
+
+        // the cheap asset of one account can't take the other asset of the pool
+        let mut cheap = pool.deposit(&[((1, 0), 1_000_000)]);
+        let mut other = pool.deposit(&[((2, 0), 1_000)]);
+        assert_eq!(
+            pool.withdraw_amounts(&mut cheap, &[((2, 0), U128(1))]),
+            Err(Flaw::CollateralAmountExceeded)
+        );
+        assert_eq!(pool.withdraw_shares(&cheap), vec![((1, 0), 1_000_000)]);
+        assert_eq!(pool.amounts["2:0"], 1_000);
+
+        assert_eq!(pool.withdraw_amounts(&mut other, &[((2, 0), U128(1_000))]), Ok(()));
+        assert!(other.is_empty());
+    }
 }
@@ -2,23 +2,23 @@ use super::*;
 use crate::config::get_bitcoin_network;
 use bitcoin::{
     hex::{Case, DisplayHex},
-    PublicKey, ScriptBuf,
+    script::{self, PushBytes},
+    PublicKey,
 };
-use message::MintBurnOption;
-use nft::NftAssetContract;
+use message::{MintBurnOption, MintNftOption};
+use nft::{NftAssetContract, NftTokenMetadata};
 use transaction_shared::Preallocated;
 
 impl Updater {
-    async fn mint_nft(
+    /// Before the nft tokens a mint is one unit of the collection, the supply is counted before
+    /// the pointer is checked.
+    async fn mint_nft_unit(
         &mut self,
         nft: &NftAssetContract,
         tx: &Transaction,
-        _block_tx: &BlockTx,
         contract_id: &BlockTxTuple,
-        mint_option: &MintBurnOption,
+        pointer: u32,
     ) -> Option<Flaw> {
-        // TODO whitelist mint
-
         if let Some(flaw) = self
             .validate_and_update_supply_cap(
                 contract_id,
@@ -33,19 +33,173 @@ impl Updater {
             return Some(flaw);
         }
 
+        if let Some(flaw) = self.validate_pointer(pointer, tx) {
+            return Some(flaw);
+        }
+
+        self.allocate_new_asset(pointer, contract_id, 1).await;
+
+        None
+    }
+
+    async fn mint_nft(
+        &mut self,
+        nft: &NftAssetContract,
+        tx: &Transaction,
+        contract_id: &BlockTxTuple,
+        pointer: u32,
+        metadata: NftTokenMetadata,
+    ) -> Option<Flaw> {
+        if !self.rule_set.nft_tokens {
+            return self.mint_nft_unit(nft, tx, contract_id, pointer).await;
+        }
+
         // check pointer overflow
-        if let Some(pointer) = mint_option.pointer {
-            if let Some(flaw) = self.validate_pointer(pointer, tx) {
-                return Some(flaw);
+        if let Some(flaw) = self.validate_pointer(pointer, tx) {
+            return Some(flaw);
+        }
+        let recipient = &tx.output[pointer as usize].script_pubkey;
+
+        // the mints are counted per allowlisted identity, per recipient without an allowlist
+        let mut minter = recipient.clone();
+        if let Some(allowlist) = &nft.allowlist {
+            // the bloom filter set by update nft replaces the one of the contract
+            let bloom_filter = match self.get_nft_contract_data(contract_id).await {
+                Ok(nft_contract_data) => nft_contract_data
+                    .whitelist_address_bloom_filter
+                    .or(allowlist.bloom_filter.clone()),
+                Err(flaw) => return Some(flaw),
+            };
+
+            // only a P2WPKH input proves the key, other inputs revealing a listed key are flawed
+            let mut allowed_pubkey = None;
+            if let Some(pubkeys) = &allowlist.pubkeys {
+                for txin in &tx.input {
+                    if !pubkeys.contains(&input_pubkey(txin)) {
+                        continue;
+                    }
+
+                    match input_p2wpkh_pubkey(txin) {
+                        Some(pubkey) => {
+                            allowed_pubkey = Some(pubkey);
+                            break;
+                        }
+                        None => return Some(Flaw::AllowlistInputNotP2wpkh),
+                    }
+                }
             }
 
-            // allocate enw asset for the mint
-            self.allocate_new_asset(pointer, contract_id, 1).await;
+            let is_address_allowed = bloom_filter.is_some_and(|bloom_filter| {
+                match Address::from_script(recipient, get_bitcoin_network()) {
+                    Ok(address) => {
+                        compressed_vec_to_bloom_filter(bloom_filter).contains(address.to_string())
+                    }
+                    Err(_) => false,
+                }
+            });
+
+            // a listed pubkey mints to any recipient, it's counted as its pay to pubkey script
+            if let Some(pubkey) = allowed_pubkey {
+                let pubkey = pubkey.to_bytes();
+                let Ok(pubkey) = <&PushBytes>::try_from(pubkey.as_slice()) else {
+                    return Some(Flaw::NotWhitelisted);
+                };
+                minter = script::Builder::new()
+                    .push_slice(pubkey)
+                    .push_opcode(opcodes::all::OP_CHECKSIG)
+                    .into_script();
+            } else if !is_address_allowed {
+                return Some(Flaw::NotWhitelisted);
+            }
+        }
+
+        let mut mint_count = 0;
+        if let Some(mint_limit_per_address) = nft.mint_limit_per_address {
+            mint_count = match self.get_nft_mint_count(contract_id, &minter).await {
+                Ok(mint_count) => mint_count,
+                Err(flaw) => return Some(flaw),
+            };
+
+            if mint_count >= mint_limit_per_address {
+                return Some(Flaw::MintLimitExceeded);
+            }
+        }
+
+        if let Some(price) = &nft.price {
+            self.paid_calls += 1;
+            let pubkey = PublicKey::from_slice(price.pay_to_key.as_slice()).unwrap();
+            if sats_paid_to_key(tx, &pubkey) < price.amount.0 {
+                return Some(Flaw::MintPriceNotPaid);
+            }
+        }
+
+        if let Some(flaw) = self
+            .validate_and_update_supply_cap(
+                contract_id,
+                nft.supply_cap.clone(),
+                1,
+                true,
+                false,
+                None,
+            )
+            .await
+        {
+            return Some(flaw);
         }
 
+        if nft.mint_limit_per_address.is_some() {
+            self.set_nft_mint_count(contract_id, &minter, mint_count + 1)
+                .await;
+        }
+
+        // every mint is a new token, the serial follows the minted supply
+        let serial = match self.get_asset_contract_data(contract_id).await {
+            Ok(data) => data.minted_supply as u64,
+            Err(flaw) => return Some(flaw),
+        };
+        self.allocate_new_nft_token(pointer, (*contract_id, serial), metadata)
+            .await;
+
         None
     }
 
+    /// Mint the next token of an nft collection with its metadata.
+    pub async fn mint_nft_token(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        mint_nft_option: &MintNftOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let message = match message {
+            Ok(message) => message,
+            Err(flaw) => return Some(flaw),
+        };
+
+        let Some(ContractType::Nft(nft)) = message
+            .contract_creation
+            .map(|contract_creation| contract_creation.contract_type)
+        else {
+            return Some(Flaw::ContractNotMatch);
+        };
+
+        if let Some(flaw) =
+            check_live_time(nft.live_time, nft.end_time, contract_id.0, block_tx.block)
+        {
+            return Some(flaw);
+        }
+
+        self.mint_nft(
+            &nft,
+            tx,
+            contract_id,
+            mint_nft_option.pointer,
+            mint_nft_option.metadata.clone(),
+        )
+        .await
+    }
+
     async fn mint_free_mint(
         &mut self,
         asset: &ContractType,
@@ -139,6 +293,9 @@ impl Updater {
         };
 
         // VALIDATE OUTPUT
+        if matches!(purchase.input_asset, InputAsset::RawBtc) || purchase.pay_to_key.is_some() {
+            self.paid_calls += 1;
+        }
         if purchase.pay_to_key.is_none() {
             // Ensure that the asset is set to burn
             match &purchase.input_asset {
@@ -161,18 +318,7 @@ impl Updater {
         } else if let Some(pubkey) = &purchase.pay_to_key {
             let bitcoin_network = get_bitcoin_network();
             let pubkey = PublicKey::from_slice(pubkey.as_slice()).unwrap();
-            let potential_addresses = vec![
-                Address::from_script(
-                    &ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().unwrap()),
-                    bitcoin_network,
-                )
-                .unwrap(),
-                Address::from_script(
-                    &ScriptBuf::new_p2pkh(&pubkey.pubkey_hash()),
-                    bitcoin_network,
-                )
-                .unwrap(),
-            ];
+            let potential_addresses = pay_to_key_addresses(&pubkey);
             for (pos, output) in tx.output.iter().enumerate() {
                 let address_from_script =
                     Address::from_script(output.script_pubkey.as_script(), bitcoin_network);
@@ -306,25 +452,7 @@ impl Updater {
 
         // validate input has utxo owned by one of the vestee
         for txin in &tx.input {
-            // P2WPKH, P2TR
-            let pubkey = if !txin.witness.is_empty() {
-                txin.witness.last().unwrap().to_vec().clone()
-            } else {
-                // P2PKH, P2PK
-                // [signature, public key]
-                let mut instructions = txin.script_sig.instructions();
-
-                if instructions.clone().count() >= 2 {
-                    if let Ok(Instruction::PushBytes(pubkey_bytes)) = &instructions.nth(1).unwrap()
-                    {
-                        pubkey_bytes.as_bytes().to_vec().clone()
-                    } else {
-                        Vec::new()
-                    }
-                } else {
-                    Vec::new()
-                }
-            };
+            let pubkey = input_pubkey(txin);
 
             for (allocation, alloc_type) in preallocated.allocations.iter() {
                 match alloc_type {
@@ -469,6 +597,52 @@ impl Updater {
             return Some(Flaw::InvalidPointer);
         }
 
+        let commitment = match &message {
+            Ok(OpReturnMessage {
+                contract_creation: Some(contract_creation),
+                ..
+            }) => match &contract_creation.contract_type {
+                ContractType::Moa(moa) => moa.commitment.clone(),
+                ContractType::Mba(mba) => mba.commitment.clone(),
+                _ => None,
+            },
+            _ => None,
+        }
+        // the commitments are enforced from the rule set that added them
+        .filter(|_| self.rule_set.check_mint_commitments);
+
+        if let Some(commitment) = &commitment {
+            if let Some(flaw) = self
+                .validate_commitment(commitment, mint_option, contract_id)
+                .await
+            {
+                return Some(flaw);
+            }
+        }
+
+        let flaw = self
+            .mint_contract(tx, block_tx, contract_id, mint_option, message)
+            .await;
+
+        // only a successful mint uses up the commitment
+        if flaw.is_none() && commitment.is_some() {
+            if let Some(commitment_message) = &mint_option.commitment_message {
+                self.set_commitment_usage(contract_id, commitment_message, block_tx)
+                    .await;
+            }
+        }
+
+        flaw
+    }
+
+    async fn mint_contract(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        mint_option: &MintBurnOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
         match message {
             Ok(op_return_message) => match op_return_message.contract_creation {
                 Some(contract_creation) => match contract_creation.contract_type.clone() {
@@ -655,7 +829,13 @@ impl Updater {
                         }
 
                         return self
-                            .mint_nft(&nft, tx, block_tx, contract_id, mint_option)
+                            .mint_nft(
+                                &nft,
+                                tx,
+                                contract_id,
+                                mint_option.pointer.unwrap(),
+                                NftTokenMetadata::default(),
+                            )
                             .await;
                     }
                 },
@@ -0,0 +1,201 @@
+use crate::config::get_bitcoin_network;
+use bitcoin::PublicKey;
+use database::{NFT_TOKENS_PREFIX, NFT_TOKEN_PREFIX};
+use message::NftTransfer;
+use nft::NftTokenMetadata;
+
+use super::*;
+
+/// Collection and serial of a token, serials start at 1 in mint order.
+pub type NftTokenId = (BlockTxTuple, u64);
+
+pub fn nft_token_key(token_id: &NftTokenId) -> String {
+    format!("{}:{}", BlockTx::from_tuple(token_id.0), token_id.1)
+}
+
+#[derive(
+    Serialize, Deserialize, BorshSerialize, BorshDeserialize, Clone, Default, Eq, PartialEq, Debug,
+)]
+pub struct NftTokens {
+    pub tokens: HashSet<NftTokenId>,
+}
+
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftToken {
+    // outpoint holding the token, None once it is spent to a transaction without outputs
+    pub owner: Option<String>,
+    pub metadata: NftTokenMetadata,
+}
+
+impl Updater {
+    impl_ops_for_outpoint_data!(NftTokens);
+
+    pub async fn get_nft_token(&self, token_id: &NftTokenId) -> Result<NftToken, Flaw> {
+        let data: Result<NftToken, DatabaseError> = self
+            .database
+            .lock()
+            .await
+            .get(NFT_TOKEN_PREFIX, &nft_token_key(token_id));
+
+        match data {
+            Ok(data) => Ok(data),
+            Err(DatabaseError::NotFound) => Err(Flaw::NotFound),
+            Err(DatabaseError::DeserializeFailed) => Err(Flaw::FailedDeserialization),
+        }
+    }
+
+    async fn set_nft_token(&self, token_id: &NftTokenId, nft_token: &NftToken) {
+        if !self.is_read_only {
+            self.database
+                .lock()
+                .await
+                .put(NFT_TOKEN_PREFIX, &nft_token_key(token_id), nft_token);
+        }
+    }
+
+    pub async fn set_nft_token_owner(
+        &self,
+        token_id: &NftTokenId,
+        owner: Option<String>,
+    ) -> Result<(), Flaw> {
+        let mut nft_token = self.get_nft_token(token_id).await?;
+        nft_token.owner = owner;
+        self.set_nft_token(token_id, &nft_token).await;
+
+        Ok(())
+    }
+
+    /// The owner of a new token is set when the outputs are committed.
+    pub async fn allocate_new_nft_token(
+        &mut self,
+        vout: u32,
+        token_id: NftTokenId,
+        metadata: NftTokenMetadata,
+    ) {
+        self.set_nft_token(
+            &token_id,
+            &NftToken {
+                owner: None,
+                metadata,
+            },
+        )
+        .await;
+
+        let allocation = self.allocated_outputs.entry(vout).or_default();
+        allocation.nft_tokens.tokens.insert(token_id);
+    }
+
+    pub async fn move_nft_token_allocation(&mut self, vout: u32, token_id: &NftTokenId) {
+        if !self.unallocated_inputs.nft_tokens.tokens.remove(token_id) {
+            return;
+        }
+
+        let allocation = self.allocated_outputs.entry(vout).or_default();
+        allocation.nft_tokens.tokens.insert(*token_id);
+    }
+
+    /// Royalties are due on the transfers with a sale price. A transfer paying one of the trusted
+    /// marketplace fee addresses of the collection is a sale, it must carry its sale price.
+    /// The outputs paying the royalty recipient must cover the royalties of every transfer.
+    async fn validate_nft_royalties(
+        &self,
+        tx: &Transaction,
+        nft_transfers: &[NftTransfer],
+    ) -> Option<Flaw> {
+        let mut royalties: HashMap<Pubkey, u128> = HashMap::new();
+
+        for nft_transfer in nft_transfers {
+            let message = match self.get_message(&nft_transfer.asset).await {
+                Ok(message) => message,
+                Err(flaw) => return Some(flaw),
+            };
+            let Some(ContractType::Nft(nft)) = message
+                .contract_creation
+                .map(|contract_creation| contract_creation.contract_type)
+            else {
+                return Some(Flaw::ContractNotMatch);
+            };
+
+            let Some(royalty) = nft.royalty else {
+                continue;
+            };
+
+            let Some(sale_price) = &nft_transfer.sale_price else {
+                let trusted_addresses = match self.get_nft_contract_data(&nft_transfer.asset).await
+                {
+                    Ok(data) => data.trusted_marketplace_fee_addresses.unwrap_or_default(),
+                    Err(flaw) => return Some(flaw),
+                };
+                let pays_marketplace = tx.output.iter().any(|output| {
+                    Address::from_script(output.script_pubkey.as_script(), get_bitcoin_network())
+                        .is_ok_and(|address| trusted_addresses.contains(&address.to_string()))
+                });
+                if pays_marketplace {
+                    return Some(Flaw::SalePriceNotFound);
+                }
+                continue;
+            };
+
+            let royalty_amount = sale_price.0.saturating_mul(royalty.fraction.0 as u128)
+                / royalty.fraction.1 as u128;
+            let due = royalties.entry(royalty.pay_to_key).or_insert(0);
+            *due = due.saturating_add(royalty_amount);
+        }
+
+        for (pay_to_key, due) in royalties {
+            let pubkey = PublicKey::from_slice(&pay_to_key).unwrap();
+            if sats_paid_to_key(tx, &pubkey) < due {
+                return Some(Flaw::RoyaltyNotPaid);
+            }
+        }
+
+        None
+    }
+
+    pub async fn nft_transfers(
+        &mut self,
+        tx: &Transaction,
+        nft_transfers: Vec<NftTransfer>,
+    ) -> Option<Flaw> {
+        // every token must be spent by the inputs, and transferred once
+        let mut token_ids = HashSet::new();
+        for nft_transfer in &nft_transfers {
+            let token_id = (nft_transfer.asset, nft_transfer.serial);
+            if !self
+                .unallocated_inputs
+                .nft_tokens
+                .tokens
+                .contains(&token_id)
+                || !token_ids.insert(token_id)
+            {
+                return Some(Flaw::NftTokenNotFound);
+            }
+        }
+
+        if let Some(flaw) = self.validate_nft_royalties(tx, &nft_transfers).await {
+            return Some(flaw);
+        }
+
+        let mut overflow_i = Vec::new();
+
+        for (i, nft_transfer) in nft_transfers.iter().enumerate() {
+            if nft_transfer.output >= tx.output.len() as u32 {
+                overflow_i.push(i as u32);
+                continue;
+            }
+            self.move_nft_token_allocation(
+                nft_transfer.output,
+                &(nft_transfer.asset, nft_transfer.serial),
+            )
+            .await;
+        }
+
+        if !overflow_i.is_empty() {
+            return Some(Flaw::OutputOverflow(overflow_i));
+        }
+
+        None
+    }
+}
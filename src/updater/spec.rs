@@ -40,6 +40,7 @@ mod test {
                 return_collateral: Some(ReturnCollateral {
                     fee: None,
                     oracle_setting: None,
+                    fee_pay_to_key: None,
                 }),
             },
             swap_mechanism: SwapMechanisms { fee: None },
@@ -90,6 +91,7 @@ mod test {
                 return_collateral: Some(ReturnCollateral {
                     fee: None,
                     oracle_setting: None,
+                    fee_pay_to_key: None,
                 }),
             },
             swap_mechanism: SwapMechanisms { fee: None },
@@ -140,6 +142,7 @@ mod test {
                 return_collateral: Some(ReturnCollateral {
                     fee: None,
                     oracle_setting: None,
+                    fee_pay_to_key: None,
                 }),
             },
             swap_mechanism: SwapMechanisms { fee: None },
@@ -190,6 +193,7 @@ mod test {
                 return_collateral: Some(ReturnCollateral {
                     fee: None,
                     oracle_setting: None,
+                    fee_pay_to_key: None,
                 }),
             },
             swap_mechanism: SwapMechanisms { fee: None },
@@ -241,6 +245,7 @@ mod test {
                 return_collateral: Some(ReturnCollateral {
                     fee: None,
                     oracle_setting: None,
+                    fee_pay_to_key: None,
                 }),
             },
             swap_mechanism: SwapMechanisms { fee: None },
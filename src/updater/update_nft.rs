@@ -0,0 +1,172 @@
+use crate::config::get_bitcoin_network;
+use bitcoin::{hex::DisplayHex, Script};
+use database::{NFT_CONTRACT_DATA_PREFIX, NFT_MINT_COUNT_PREFIX};
+use message::UpdateNftOption;
+
+use super::*;
+
+#[serde_with::skip_serializing_none]
+#[derive(Deserialize, Serialize, BorshDeserialize, BorshSerialize, Clone, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct NftContractData {
+    pub whitelist_address_bloom_filter: Option<Vec<u8>>,
+    pub trusted_marketplace_fee_addresses: Option<Vec<String>>,
+}
+
+fn nft_mint_count_key(contract_id: &BlockTxTuple, script_pubkey: &Script) -> String {
+    format!(
+        "{}:{}",
+        BlockTx::from_tuple(*contract_id),
+        script_pubkey.as_bytes().to_lower_hex_string()
+    )
+}
+
+impl Updater {
+    pub async fn get_nft_contract_data(
+        &self,
+        contract_id: &BlockTxTuple,
+    ) -> Result<NftContractData, Flaw> {
+        let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+        let data: Result<NftContractData, DatabaseError> = self
+            .database
+            .lock()
+            .await
+            .get(NFT_CONTRACT_DATA_PREFIX, &contract_key);
+
+        match data {
+            Ok(data) => Ok(data),
+            Err(DatabaseError::NotFound) => Ok(NftContractData::default()),
+            Err(DatabaseError::DeserializeFailed) => Err(Flaw::FailedDeserialization),
+        }
+    }
+
+    async fn set_nft_contract_data(
+        &self,
+        contract_id: &BlockTxTuple,
+        nft_contract_data: &NftContractData,
+    ) {
+        if !self.is_read_only {
+            let contract_key = BlockTx::from_tuple(*contract_id).to_string();
+            self.database.lock().await.put(
+                NFT_CONTRACT_DATA_PREFIX,
+                &contract_key,
+                nft_contract_data,
+            );
+        }
+    }
+
+    /// Mints of the nft contract by the minter, the script of its allowlisted identity.
+    pub async fn get_nft_mint_count(
+        &self,
+        contract_id: &BlockTxTuple,
+        script_pubkey: &Script,
+    ) -> Result<u32, Flaw> {
+        let data: Result<u32, DatabaseError> = self
+            .database
+            .lock()
+            .await
+            .get(NFT_MINT_COUNT_PREFIX, &nft_mint_count_key(contract_id, script_pubkey));
+
+        match data {
+            Ok(data) => Ok(data),
+            Err(DatabaseError::NotFound) => Ok(0),
+            Err(DatabaseError::DeserializeFailed) => Err(Flaw::FailedDeserialization),
+        }
+    }
+
+    pub async fn set_nft_mint_count(
+        &self,
+        contract_id: &BlockTxTuple,
+        script_pubkey: &Script,
+        mint_count: u32,
+    ) {
+        if !self.is_read_only {
+            self.database.lock().await.put(
+                NFT_MINT_COUNT_PREFIX,
+                &nft_mint_count_key(contract_id, script_pubkey),
+                mint_count,
+            );
+        }
+    }
+
+    /// The access key is a state key of the nft contract. It is issued to `access_key_pointer`
+    /// when the update is called in the contract creation, later updates must spend it.
+    /// An nft created without an access key can't be updated.
+    pub async fn update_nft(
+        &mut self,
+        tx: &Transaction,
+        block_tx: &BlockTx,
+        contract_id: &BlockTxTuple,
+        update_nft_option: &UpdateNftOption,
+        message: Result<OpReturnMessage, Flaw>,
+    ) -> Option<Flaw> {
+        let message = match message {
+            Ok(message) => message,
+            Err(flaw) => return Some(flaw),
+        };
+
+        match message.contract_creation {
+            Some(contract_creation) => {
+                if !matches!(contract_creation.contract_type, ContractType::Nft(_)) {
+                    return Some(Flaw::ContractNotMatch);
+                }
+            }
+            None => return Some(Flaw::ContractNotMatch),
+        }
+
+        let is_contract_creation = *contract_id == block_tx.to_tuple();
+        if !is_contract_creation
+            && !self
+                .unallocated_inputs
+                .state_keys
+                .contract_ids
+                .contains(contract_id)
+        {
+            return Some(Flaw::StateKeyNotFound);
+        }
+
+        let mut nft_contract_data = match self.get_nft_contract_data(contract_id).await {
+            Ok(data) => data,
+            Err(flaw) => return Some(flaw),
+        };
+
+        if let Some(filter) = &update_nft_option.whitelist_address_bloom_filter {
+            if try_compressed_vec_to_bloom_filter(filter.clone()).is_none() {
+                return Some(Flaw::InvalidBloomFilter);
+            }
+            nft_contract_data.whitelist_address_bloom_filter = Some(filter.clone());
+        }
+
+        if let Some(addresses) = &update_nft_option.trusted_marketplace_fee_addresses {
+            for address in addresses {
+                let is_valid = Address::from_str(address)
+                    .map(|address| address.is_valid_for_network(get_bitcoin_network()))
+                    .unwrap_or(false);
+                if !is_valid {
+                    return Some(Flaw::InvalidBitcoinAddress);
+                }
+            }
+            nft_contract_data.trusted_marketplace_fee_addresses = Some(addresses.clone());
+        }
+
+        if let Some(pointer) = update_nft_option.access_key_pointer {
+            let Ok(pointer) = u32::try_from(pointer) else {
+                return Some(Flaw::PointerOverflow);
+            };
+            if let Some(flaw) = self.validate_pointer(pointer, tx) {
+                return Some(flaw);
+            }
+
+            if is_contract_creation {
+                self.allocate_new_state_key(pointer, contract_id).await;
+            } else {
+                self.move_state_keys_allocation(pointer, contract_id).await;
+            }
+        }
+
+        self.set_nft_contract_data(contract_id, &nft_contract_data)
+            .await;
+
+        None
+    }
+}
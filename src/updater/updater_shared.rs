@@ -1,11 +1,24 @@
 use base64::{engine::general_purpose, Engine};
 use growable_bloom_filter::GrowableBloom;
-use message::{AssertValues, MintBurnOption, OracleMessageSigned};
+use crate::config::get_bitcoin_network;
+use bitcoin::{
+    hashes::HashEngine,
+    secp256k1::ecdsa::Signature as EcdsaSignature,
+    PublicKey, ScriptBuf, TxIn,
+};
+use message::{AssertValues, Commitment, CommitmentMessage, MintBurnOption, OracleMessageSigned};
 use miniz_oxide::{deflate::compress_to_vec, inflate::decompress_to_vec};
 use transaction_shared::{OracleSetting, RatioType};
 
 use super::*;
 
+// ECIES commitment: ephemeral public key, AES-GCM nonce, then the ciphertext and its tag,
+// followed by the compact signature of the commitment key over `commitment_digest`
+const COMMITMENT_EPHEMERAL_KEY_LEN: usize = 33;
+const COMMITMENT_NONCE_LEN: usize = 12;
+const COMMITMENT_TAG_LEN: usize = 16;
+const COMMITMENT_SIGNATURE_LEN: usize = 64;
+
 pub fn relative_block_height_to_block_height(
     block_height_relative_absolute: RelativeOrAbsoluteBlockHeight,
     current_block_height: BlockHeight,
@@ -76,6 +89,116 @@ pub fn compressed_vec_to_bloom_filter(input: Vec<u8>) -> GrowableBloom {
     filter
 }
 
+/// Same as `compressed_vec_to_bloom_filter`, None when the input is not a compressed filter.
+pub fn try_compressed_vec_to_bloom_filter(input: Vec<u8>) -> Option<GrowableBloom> {
+    let decompressed =
+        decompress_to_vec(general_purpose::STANDARD.decode(input).ok()?.as_slice()).ok()?;
+
+    let str = String::from_utf8(decompressed).ok()?;
+
+    serde_json::from_str(&str).ok()
+}
+
+/// Pubkey revealed by the input, empty when there is none.
+pub fn input_pubkey(txin: &TxIn) -> Vec<u8> {
+    // P2WPKH, P2TR
+    if !txin.witness.is_empty() {
+        return txin.witness.last().unwrap().to_vec();
+    }
+
+    // P2PKH, P2PK
+    // [signature, public key]
+    let mut instructions = txin.script_sig.instructions();
+
+    if instructions.clone().count() >= 2 {
+        if let Ok(Instruction::PushBytes(pubkey_bytes)) = &instructions.nth(1).unwrap() {
+            return pubkey_bytes.as_bytes().to_vec();
+        }
+    }
+
+    Vec::new()
+}
+
+/// Pubkey of a P2WPKH input, the witness is [signature, compressed public key]. Other inputs
+/// don't reveal their key without the previous output, a P2TR key path spend only has a signature.
+pub fn input_p2wpkh_pubkey(txin: &TxIn) -> Option<PublicKey> {
+    if !txin.script_sig.is_empty() || txin.witness.len() != 2 {
+        return None;
+    }
+
+    bitcoin::ecdsa::Signature::from_slice(txin.witness.nth(0)?).ok()?;
+    let pubkey = PublicKey::from_slice(txin.witness.nth(1)?).ok()?;
+
+    pubkey.compressed.then_some(pubkey)
+}
+
+/// Addresses that pay to the pubkey, P2WPKH and P2PKH.
+pub fn pay_to_key_addresses(pubkey: &PublicKey) -> Vec<Address> {
+    let bitcoin_network = get_bitcoin_network();
+
+    vec![
+        Address::from_script(
+            &ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash().unwrap()),
+            bitcoin_network,
+        )
+        .unwrap(),
+        Address::from_script(&ScriptBuf::new_p2pkh(&pubkey.pubkey_hash()), bitcoin_network)
+            .unwrap(),
+    ]
+}
+
+/// Sats of the outputs paying to the pubkey.
+pub fn sats_paid_to_key(tx: &Transaction, pubkey: &PublicKey) -> u128 {
+    let potential_addresses = pay_to_key_addresses(pubkey);
+
+    tx.output
+        .iter()
+        .filter(|output| {
+            Address::from_script(output.script_pubkey.as_script(), get_bitcoin_network())
+                .is_ok_and(|address| potential_addresses.contains(&address))
+        })
+        .map(|output| output.value.to_sat() as u128)
+        .sum()
+}
+
+/// First output paying to the pubkey.
+pub fn output_paying_to_key(tx: &Transaction, pubkey: &PublicKey) -> Option<u32> {
+    let potential_addresses = pay_to_key_addresses(pubkey);
+
+    tx.output
+        .iter()
+        .position(|output| {
+            Address::from_script(output.script_pubkey.as_script(), get_bitcoin_network())
+                .is_ok_and(|address| potential_addresses.contains(&address))
+        })
+        .map(|vout| vout as u32)
+}
+
+/// Digest signed by the commitment key of the contract to issue an encrypted payload, the
+/// signature is appended to the payload in the commitment message.
+pub fn commitment_digest(contract_id: &BlockTxTuple, encrypted_args: &[u8]) -> sha256::Hash {
+    let mut engine = sha256::Hash::engine();
+    engine.input(BlockTx::from_tuple(*contract_id).to_string().as_bytes());
+    engine.input(encrypted_args);
+    sha256::Hash::from_engine(engine)
+}
+
+/// Splits the commitment message args into the encrypted payload and its signature.
+fn split_commitment_args(args: &[u8]) -> (&[u8], &[u8]) {
+    args.split_at(args.len().saturating_sub(COMMITMENT_SIGNATURE_LEN))
+}
+
+/// Commitments are keyed by contract and the signed digest, the signature can't be moved to
+/// another payload so a re-encrypted payload needs a new signature of the contract.
+pub fn commitment_key(contract_id: &BlockTxTuple, commitment_message: &CommitmentMessage) -> String {
+    let (encrypted_args, _) = split_commitment_args(&commitment_message.args);
+    format!(
+        "{}:{}",
+        BlockTx::from_tuple(*contract_id),
+        commitment_digest(contract_id, encrypted_args)
+    )
+}
+
 impl Updater {
     pub async fn validate_ticker_not_exist(&self, ticker: String) -> Option<Flaw> {
         let contract_block_tx = self.get_contract_block_tx_by_ticker(ticker).await;
@@ -184,7 +307,10 @@ impl Updater {
                 }
                 data.minted_supply_by_freemint = next_supply_free_mint;
             }
+        } else if self.rule_set.track_burned_supply {
+            data.burned_supply = data.burned_supply.saturating_add(amount);
         } else {
+            // the burned supply stays at zero before the rule set
             data.burned_supply = data.burned_supply.saturating_sub(amount);
         }
 
@@ -323,4 +449,62 @@ impl Updater {
 
         None
     }
+
+    /// The commitment is a signed opaque payload: the indexer can't decrypt it, so it only checks
+    /// a well-formed ECIES envelope, the signature of the contract's public key over it and that
+    /// it was never used before on the contract. The `fixed_string:string` template is checked
+    /// off-chain by the holder of the contract key before signing.
+    pub async fn validate_commitment(
+        &self,
+        commitment: &Commitment,
+        mint_option: &MintBurnOption,
+        contract_id: &BlockTxTuple,
+    ) -> Option<Flaw> {
+        let Some(commitment_message) = &mint_option.commitment_message else {
+            return Some(Flaw::CommitmentMessageNotFound);
+        };
+
+        if commitment_message.public_key != commitment.public_key {
+            return Some(Flaw::CommitmentPubkeyMismatch);
+        }
+
+        // at least one byte of ciphertext
+        let min_len = COMMITMENT_EPHEMERAL_KEY_LEN
+            + COMMITMENT_NONCE_LEN
+            + COMMITMENT_TAG_LEN
+            + 1
+            + COMMITMENT_SIGNATURE_LEN;
+        if commitment_message.args.len() < min_len
+            || PublicKey::from_slice(&commitment_message.args[..COMMITMENT_EPHEMERAL_KEY_LEN])
+                .is_err()
+        {
+            return Some(Flaw::CommitmentMessageInvalid);
+        }
+
+        let (encrypted_args, signature) = split_commitment_args(&commitment_message.args);
+        let Ok(signature) = EcdsaSignature::from_compact(signature) else {
+            return Some(Flaw::CommitmentSignatureInvalid);
+        };
+        let Ok(public_key) = PublicKey::from_slice(&commitment.public_key) else {
+            return Some(Flaw::PubkeyInvalid);
+        };
+        let msg = Message::from_digest(
+            commitment_digest(contract_id, encrypted_args).to_byte_array(),
+        );
+        if Secp256k1::verification_only()
+            .verify_ecdsa(&msg, &signature, &public_key.inner)
+            .is_err()
+        {
+            return Some(Flaw::CommitmentSignatureInvalid);
+        }
+
+        match self
+            .get_commitment_usage(contract_id, commitment_message)
+            .await
+        {
+            Ok(Some(_)) => Some(Flaw::CommitmentAlreadyUsed),
+            Ok(None) => None,
+            Err(flaw) => Some(flaw),
+        }
+    }
 }
@@ -0,0 +1,162 @@
+use super::*;
+
+use std::time::Duration;
+use tokio::{
+    sync::Notify,
+    time::{sleep, sleep_until, Instant},
+};
+use zeromq::{Socket, SocketRecv, SubSocket, ZmqResult};
+
+const ZMQ_TOPICS: [&str; 3] = ["hashblock", "rawblock", "rawtx"];
+// every wake re-evaluates the whole mempool, transactions arrive continuously
+const RAWTX_WAKE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Wake the indexer on every bitcoind block notification, transaction notifications are
+/// coalesced into at most one wake per `RAWTX_WAKE_INTERVAL`.
+/// The subscriber reconnects when the socket fails, the indexer keeps polling in the meantime.
+pub async fn run_zmq_subscriber(zmq_url: String, notify: Arc<Notify>) {
+    loop {
+        if let Err(error) = subscribe(&zmq_url, &notify, RAWTX_WAKE_INTERVAL).await {
+            log::warn!("ZMQ subscriber {} failed: {}", zmq_url, error);
+        }
+
+        sleep(Duration::from_secs(10)).await;
+    }
+}
+
+async fn subscribe(zmq_url: &str, notify: &Notify, rawtx_wake_interval: Duration) -> ZmqResult<()> {
+    let mut socket = SubSocket::new();
+    socket.connect(zmq_url).await?;
+    for topic in ZMQ_TOPICS {
+        socket.subscribe(topic).await?;
+    }
+
+    log::info!("Listening to ZMQ notifications on {}", zmq_url);
+    let mut rawtx_wakes = RawtxWakes::new(rawtx_wake_interval, Instant::now());
+    loop {
+        let due = rawtx_wakes.due();
+        let message = tokio::select! {
+            message = socket.recv() => message?,
+            // the transactions received since the last wake
+            _ = sleep_until(due.unwrap_or_else(Instant::now)), if due.is_some() => {
+                rawtx_wakes.woken(Instant::now());
+                notify.notify_one();
+                continue;
+            }
+        };
+
+        let topic = message
+            .get(0)
+            .map(|topic| topic.to_vec())
+            .unwrap_or_default();
+        log::debug!("ZMQ {} notification", String::from_utf8_lossy(&topic));
+
+        if rawtx_wakes.notified(&topic, Instant::now()) {
+            notify.notify_one();
+        }
+    }
+}
+
+/// Coalesces the rawtx notifications into at most one wake per interval.
+struct RawtxWakes {
+    interval: Duration,
+    next_wake: Instant,
+    pending: bool,
+}
+
+impl RawtxWakes {
+    fn new(interval: Duration, now: Instant) -> Self {
+        RawtxWakes {
+            interval,
+            next_wake: now,
+            pending: false,
+        }
+    }
+
+    /// Whether the notification wakes the indexer right away, otherwise the wake is due later.
+    fn notified(&mut self, topic: &[u8], now: Instant) -> bool {
+        if topic == b"rawtx" {
+            if now < self.next_wake {
+                self.pending = true;
+                return false;
+            }
+            self.next_wake = now + self.interval;
+        }
+
+        // a block wake re-evaluates the mempool too
+        self.pending = false;
+        true
+    }
+
+    /// When the coalesced transactions wake the indexer, None without pending transactions.
+    fn due(&self) -> Option<Instant> {
+        self.pending.then_some(self.next_wake)
+    }
+
+    fn woken(&mut self, now: Instant) {
+        self.pending = false;
+        self.next_wake = now + self.interval;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::time::timeout;
+    use zeromq::{PubSocket, SocketSend};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_zmq_notification_wakes_indexer() {
+        let mut publisher = PubSocket::new();
+        let endpoint = publisher.bind("tcp://127.0.0.1:0").await.unwrap();
+
+        let notify = Arc::new(Notify::new());
+        tokio::spawn(run_zmq_subscriber(
+            endpoint.to_string(),
+            Arc::clone(&notify),
+        ));
+
+        // the subscription reaches the publisher asynchronously, publish until it's received
+        let woken = timeout(Duration::from_secs(5), async {
+            loop {
+                publisher.send("hashblock".into()).await.unwrap();
+
+                if timeout(Duration::from_millis(100), notify.notified())
+                    .await
+                    .is_ok()
+                {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        assert!(woken.is_ok());
+    }
+
+    #[test]
+    fn test_zmq_rawtx_wakes_coalesced() {
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        let mut rawtx_wakes = RawtxWakes::new(5 * second, start);
+
+        assert!(rawtx_wakes.notified(b"rawtx", start));
+        assert_eq!(rawtx_wakes.due(), None);
+
+        // the next transactions wake the indexer once the interval is over
+        assert!(!rawtx_wakes.notified(b"rawtx", start + second));
+        assert!(!rawtx_wakes.notified(b"rawtx", start + 2 * second));
+        assert_eq!(rawtx_wakes.due(), Some(start + 5 * second));
+
+        rawtx_wakes.woken(start + 5 * second);
+        assert_eq!(rawtx_wakes.due(), None);
+        assert!(!rawtx_wakes.notified(b"rawtx", start + 6 * second));
+        assert_eq!(rawtx_wakes.due(), Some(start + 10 * second));
+
+        // a block wakes right away and covers the pending transactions
+        assert!(rawtx_wakes.notified(b"hashblock", start + 7 * second));
+        assert_eq!(rawtx_wakes.due(), None);
+        assert!(rawtx_wakes.notified(b"rawtx", start + 10 * second));
+    }
+}